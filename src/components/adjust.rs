@@ -0,0 +1,155 @@
+use leptos::prelude::*;
+use thaw::{Toast, ToastBody, ToastTitle, ToasterInjection};
+
+use crate::teamgen::{team_label, Adjustment, Constraints, GeneratedTeams, Move, Player, Side};
+use crate::worker::{AdjustRequest, Generation, Job, Response};
+
+fn describe_side(side: Side) -> String {
+    match side {
+        Side::Team(t) => format!("Team {}", team_label(t)),
+        Side::Shared => "the shared spot".to_string(),
+    }
+}
+
+fn describe_move(m: &Move) -> String {
+    match (m.from, m.to) {
+        (None, Some(to)) => format!("{} joins {}", m.name, describe_side(to)),
+        (Some(from), None) => format!("{} leaves {}", m.name, describe_side(from)),
+        (Some(from), Some(to)) => format!("{} moves from {} to {}", m.name, describe_side(from), describe_side(to)),
+        (None, None) => m.name.clone(),
+    }
+}
+
+/// Adds a late arrival to, or takes a no-show off, the current teams, moving as few players as it takes to rebalance.
+#[component]
+pub fn Adjust(
+    /// The roster, with the ratings the generator uses
+    players: Signal<Vec<Player>>,
+    constraints: Signal<Constraints>,
+    teams: RwSignal<Vec<Vec<Player>>>,
+    team_shared: RwSignal<Option<Player>>,
+    team_gap: RwSignal<Option<f32>>,
+    candidates: RwSignal<Vec<GeneratedTeams>>,
+) -> impl IntoView {
+    let toaster = ToasterInjection::expect_context();
+    let arrival = RwSignal::new(String::new());
+    let departure = RwSignal::new(String::new());
+    let moves = RwSignal::new(Vec::<Move>::new());
+    // The search runs in a worker, as trying every few moves on a big roster takes a while.
+    let running = StoredValue::new_local(None::<Generation>);
+    let busy = RwSignal::new(false);
+    // Freshly generated teams start over.
+    Effect::new(move || if !candidates.get().is_empty() {
+        moves.set(vec![]);
+    });
+
+    let playing = Signal::derive(move || teams.get().into_iter()
+        .flatten()
+        .chain(team_shared.get())
+        .map(|p| p.name)
+        .collect::<Vec<_>>());
+
+    let show_adjusted = move |result: Result<Adjustment, String>| {
+        match result {
+            Ok(Adjustment { teams: adjusted, shared, delta, constraints_met, moves: made }) => {
+                teams.set(adjusted);
+                team_shared.set(shared);
+                team_gap.set(Some(delta));
+                // The other candidates were made for the old roster.
+                candidates.set(vec![]);
+                moves.set(made);
+                if !constraints_met {
+                    toaster.dispatch_toast(move || view! {
+                        <Toast>
+                            <ToastTitle>"Showing The Closest Adjustment Found"</ToastTitle>
+                            <ToastBody>
+                                {format!("These teams are {delta} apart. Generating new teams may get them closer.")}
+                            </ToastBody>
+                        </Toast>
+                    }, Default::default());
+                }
+            },
+            Err(e) => {
+                toaster.dispatch_toast(move || view! {
+                    <Toast>
+                        <ToastTitle>"Failed To Adjust Teams"</ToastTitle>
+                        <ToastBody>{e}</ToastBody>
+                    </Toast>
+                }, Default::default());
+            }
+        }
+    };
+
+    let adjust = move |arrivals: Vec<Player>, departures: Vec<String>| {
+        let request = AdjustRequest {
+            teams: teams.get(),
+            shared: team_shared.get(),
+            arrivals,
+            departures,
+            constraints: constraints.get(),
+        };
+        let on_response = move |response| if let Response::Adjusted(result) = response {
+            busy.set(false);
+            show_adjusted(result);
+        };
+        running.set_value(None);
+        match Generation::start(Job::Adjust(request), on_response) {
+            Ok(started) => {
+                running.set_value(Some(started));
+                busy.set(true);
+            },
+            Err(e) => {
+                toaster.dispatch_toast(move || view! {
+                    <Toast>
+                        <ToastTitle>"Failed To Start Adjusting"</ToastTitle>
+                        <ToastBody>{format!("{e:?}")}</ToastBody>
+                    </Toast>
+                }, Default::default());
+            }
+        }
+    };
+
+    let add_arrival = move |_| {
+        let Some(p) = players.get().into_iter().find(|p| p.name == arrival.get()) else {
+            return;
+        };
+        adjust(vec![p], vec![]);
+    };
+
+    let remove_departure = move |_| {
+        let name = departure.get();
+        if !name.is_empty() {
+            adjust(vec![], vec![name]);
+        }
+    };
+
+    view! {
+        { move || (!teams.get().is_empty()).then(|| view! {
+            <div class="col">
+                <h3>"Adjust Teams"</h3>
+                <div class="row">
+                    <select on:change=move |ev| arrival.set(event_target_value(&ev))>
+                        <option value="">"Late arrival..."</option>
+                        { move || players.get().into_iter()
+                            .filter(|p| !playing.get().contains(&p.name))
+                            .map(|p| view! { <option value=p.name.clone()>{p.name.clone()}</option> })
+                            .collect_view() }
+                    </select>
+                    <button disabled=move || busy.get() on:click=add_arrival>"Add To Teams"</button>
+                    <select on:change=move |ev| departure.set(event_target_value(&ev))>
+                        <option value="">"No-show..."</option>
+                        { move || playing.get().into_iter()
+                            .map(|name| view! { <option value=name.clone()>{name.clone()}</option> })
+                            .collect_view() }
+                    </select>
+                    <button disabled=move || busy.get() on:click=remove_departure>"Take Off Teams"</button>
+                </div>
+                <ul id="adjust-moves">
+                    { move || moves.get().iter()
+                        .map(|m| view! { <li>{describe_move(m)}</li> })
+                        .collect_view() }
+                </ul>
+            </div>
+        }) }
+    }
+}
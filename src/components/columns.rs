@@ -0,0 +1,62 @@
+use leptos::prelude::*;
+
+use crate::sheet::{column_for, Column, ColumnMap, Field};
+
+/// The select value for what a column holds.
+fn column_value(column: Column) -> String {
+    match column {
+        Column::Field(field) => Field::ALL.iter().position(|&f| f == field).unwrap().to_string(),
+        Column::Attribute => "attribute".to_string(),
+        Column::Ignored => "ignored".to_string(),
+    }
+}
+
+/// Lists a sheet's headers, and lets each be read as a player field, an extra rating, or nothing.
+#[component]
+pub fn ColumnMapping(headers: Signal<Vec<String>>, mapping: RwSignal<ColumnMap>) -> impl IntoView {
+    view! {
+        <h3>"Sheet Columns"</h3>
+        <table id="column-mapping">
+            <tr>
+                <th> Header </th><th> Read As </th>
+            </tr>
+            { move || headers.get().into_iter()
+                .map(|header| {
+                    let selected = column_value(column_for(&header, &mapping.get_untracked()));
+                    let update_column = {
+                        let header = header.clone();
+                        move |ev| {
+                            let v = event_target_value(&ev);
+                            let column = match v.as_str() {
+                                "attribute" => Column::Attribute,
+                                "ignored" => Column::Ignored,
+                                i => Column::Field(Field::ALL[i.parse::<usize>().unwrap()]),
+                            };
+                            mapping.update(|mapping| { mapping.insert(header.trim().to_string(), column); });
+                        }
+                    };
+                    view! { <tr>
+                        <td>{header}</td>
+                        <td>
+                            <select on:change=update_column>
+                                { Field::ALL.into_iter()
+                                    .map(|field| {
+                                        let value = column_value(Column::Field(field));
+                                        view! {
+                                            <option value=value.clone() selected=value == selected>{field.label()}</option>
+                                        }
+                                    })
+                                    .collect_view() }
+                                <option value="attribute" selected=selected == "attribute">"Extra Rating"</option>
+                                <option value="ignored" selected=selected == "ignored">"Ignored"</option>
+                            </select>
+                        </td>
+                    </tr> }
+                })
+                .collect_view() }
+        </table>
+        <div class="row">
+            <button on:click=move |_| mapping.set(ColumnMap::new())>"Reset Columns"</button>
+        </div>
+    }
+}
@@ -0,0 +1,124 @@
+use leptos::prelude::*;
+
+use crate::draft::{auto_captains, draft_gap, snake_turn, suggest_pick};
+use crate::teamgen::{team_label, GeneratedTeams, Player};
+
+/// Lets captains pick teams in a snake draft from the rated roster in `players`, filling in `teams` as they go.
+#[component]
+pub fn Draft(
+    players: Signal<Vec<Player>>,
+    num_teams: RwSignal<usize>,
+    teams: RwSignal<Vec<Vec<Player>>>,
+    team_shared: RwSignal<Option<Player>>,
+    team_gap: RwSignal<Option<f32>>,
+    candidates: RwSignal<Vec<GeneratedTeams>>,
+) -> impl IntoView {
+    // The captain chosen for each team, by name.
+    let captains = RwSignal::new(Vec::<String>::new());
+    Effect::new(move || captains.set(vec![String::new(); num_teams.get()]));
+
+    // Each pick so far: the team, the player, and the rating gap once they joined.
+    let picks = RwSignal::new(Vec::<(usize, String, f32)>::new());
+    let drafting = RwSignal::new(false);
+    // Why the draft couldn't start with the captains chosen.
+    let captain_error = RwSignal::new(None::<String>);
+
+    let pool = Signal::derive(move || {
+        let teams = teams.get();
+        players.get().into_iter()
+            .filter(|p| !teams.iter().flatten().any(|q| q.name == p.name))
+            .collect::<Vec<_>>()
+    });
+    let suggestion = Signal::derive(move || {
+        suggest_pick(&teams.get(), &pool.get(), picks.get().len())
+            .map(|i| pool.get()[i].name.clone())
+    });
+
+    let start_draft = move |_| {
+        let players = players.get();
+        let chosen = captains.get();
+        let duplicate = chosen.iter()
+            .enumerate()
+            .find(|&(i, name)| !name.is_empty() && chosen[..i].contains(name));
+        if let Some((_, name)) = duplicate {
+            captain_error.set(Some(format!("{name} can only captain one team.")));
+            return;
+        }
+        captain_error.set(None);
+        // Automatic captains are picked afresh each draft, leaving their slots on Auto.
+        let chosen = auto_captains(&players, &chosen);
+        let drafted = chosen.iter()
+            .map(|name| players.iter().filter(|p| &p.name == name).cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        team_gap.set(Some(draft_gap(&drafted)));
+        teams.set(drafted);
+        team_shared.set(None);
+        candidates.set(vec![]);
+        picks.set(vec![]);
+        drafting.set(true);
+    };
+
+    let pick = move |name: String| {
+        let Some(player) = pool.get().into_iter().find(|p| p.name == name) else {
+            return;
+        };
+        let team = snake_turn(picks.get().len(), teams.get().len());
+        teams.update(|teams| teams[team].push(player));
+        let gap = draft_gap(&teams.get());
+        team_gap.set(Some(gap));
+        picks.update(|picks| picks.push((team, name, gap)));
+    };
+
+    view! {
+        <div class="col">
+            <h3>"Captain Draft"</h3>
+            <div class="row">
+                { move || (0..num_teams.get())
+                    .map(|t| view! {
+                        <label class="team-delta-label">"Team "{team_label(t)}" Captain:"</label>
+                        <select on:change=move |ev| {
+                            let v = event_target_value(&ev);
+                            captains.update(|captains| captains[t] = v);
+                        }>
+                            <option value="">"Auto"</option>
+                            { players.get().into_iter()
+                                .map(|p| view! { <option value=p.name.clone()>{p.name.clone()}</option> })
+                                .collect_view() }
+                        </select>
+                    })
+                    .collect_view() }
+                <button on:click=start_draft>"Start Draft"</button>
+            </div>
+            { move || captain_error.get().map(|e| view! { <p class="validation-error">{e}</p> }) }
+            { move || drafting.get().then(|| view! {
+                <Show when=move || !pool.get().is_empty() fallback=|| view! { <span>"Draft complete."</span> }>
+                    <span>"Team "{move || team_label(snake_turn(picks.get().len(), teams.get().len()))}" is picking."</span>
+                </Show>
+                <div class="row">
+                    { move || pool.get().into_iter()
+                        .map(|p| {
+                            let name = p.name.clone();
+                            let suggested = suggestion.get().as_ref() == Some(&p.name);
+                            view! {
+                                <button
+                                    class:suggested-pick=suggested
+                                    on:click=move |_| pick(name.clone())
+                                >{p.name.clone()}" ("{p.rating}")"</button>
+                            }
+                        })
+                        .collect_view() }
+                </div>
+                <table id="draft-picks">
+                    { move || picks.get().into_iter()
+                        .enumerate()
+                        .map(|(i, (team, name, gap))| view! { <tr>
+                            <td>{i + 1}"."</td>
+                            <td>"Team "{team_label(team)}" picks "{name}</td>
+                            <td>"Rating Gap: "{gap}</td>
+                        </tr> })
+                        .collect_view() }
+                </table>
+            }) }
+        </div>
+    }
+}
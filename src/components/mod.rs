@@ -1,2 +1,10 @@
+pub mod adjust;
+pub mod columns;
+pub mod draft;
+pub mod pairings;
+pub mod pitch;
 pub mod players;
+pub mod positions;
+pub mod results;
+pub mod shared;
 pub mod teamgen;
\ No newline at end of file
@@ -0,0 +1,74 @@
+use leptos::{ev::SubmitEvent, prelude::*};
+
+use crate::teamgen::{Pairing, Player};
+
+/// Lists and edits the rules for which players must, or must not, share a team.
+#[component]
+pub fn Pairings(players: RwSignal<Vec<Player>>, pairings: RwSignal<Vec<Pairing>>) -> impl IntoView {
+    let first = RwSignal::new(String::new());
+    let second = RwSignal::new(String::new());
+    let together = RwSignal::new(true);
+
+    let add_pairing = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let (first, second) = (first.get(), second.get());
+        if first.is_empty() || second.is_empty() || first == second {
+            return;
+        }
+        let pairing = Pairing { first, second, together: together.get() };
+        pairings.update(|pairings| {
+            if !pairings.contains(&pairing) {
+                pairings.push(pairing);
+            }
+        });
+    };
+
+    let player_options = move || players.get().into_iter()
+        .map(|p| view! { <option value=p.name.clone()>{p.name.clone()}</option> })
+        .collect_view();
+
+    let describe = |p: &Pairing| if p.together { "must play with" } else { "must not play with" };
+
+    view! {
+        <form class="row" on:submit=add_pairing>
+            <select on:change=move |ev| first.set(event_target_value(&ev))>
+                <option value="">"Player..."</option>
+                { player_options }
+            </select>
+            <select on:change=move |ev| together.set(event_target_value(&ev) == "together")>
+                <option value="together">"must play with"</option>
+                <option value="apart">"must not play with"</option>
+            </select>
+            <select on:change=move |ev| second.set(event_target_value(&ev))>
+                <option value="">"Player..."</option>
+                { player_options }
+            </select>
+            <button type="submit">"Add Rule"</button>
+        </form>
+        <table id="pairing-listing">
+            { move || pairings.get().into_iter()
+                .enumerate()
+                .map(|(i, p)| view! { <tr>
+                    <td>{p.first.clone()}</td>
+                    <td>{describe(&p)}</td>
+                    <td>{p.second.clone()}</td>
+                    <td>
+                        <button on:click=move |_| pairings.update(|pairings| { pairings.remove(i); })>
+                            "Remove"
+                        </button>
+                    </td>
+                </tr> })
+                .collect_view() }
+            // Rules from the sheet can only be changed on the sheet.
+            { move || players.get().into_iter()
+                .flat_map(|p| p.pairings)
+                .map(|p| view! { <tr>
+                    <td>{p.first.clone()}</td>
+                    <td>{describe(&p)}</td>
+                    <td>{p.second.clone()}</td>
+                    <td>"(from sheet)"</td>
+                </tr> })
+                .collect_view() }
+        </table>
+    }
+}
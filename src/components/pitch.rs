@@ -0,0 +1,43 @@
+use leptos::prelude::*;
+
+use crate::formation::{assign_formation, Line};
+use crate::positions::PositionCatalog;
+use crate::teamgen::Player;
+
+/// Draws a team on a pitch in the given formation, attackers at the top.
+#[component]
+pub fn Pitch(
+    team: Vec<Player>,
+    formation: Vec<Line>,
+    positions: PositionCatalog,
+    color: String,
+    text_color: &'static str,
+) -> impl IntoView {
+    let assignment = assign_formation(&team, &formation, &positions);
+
+    view! {
+        <div class="col">
+            <div class="pitch">
+                { assignment.lines.into_iter()
+                    .rev()
+                    .map(|line| view! {
+                        <div class="pitch-line">
+                            { line.into_iter()
+                                .map(|slot| view! {
+                                    <span
+                                        class="pitch-slot"
+                                        style:background-color=color.clone()
+                                        style:color=text_color
+                                    >{slot.unwrap_or_else(|| "?".to_string())}</span>
+                                })
+                                .collect_view() }
+                        </div>
+                    })
+                    .collect_view() }
+            </div>
+            { (!assignment.subs.is_empty()).then(|| view! {
+                <span>"Subs: "{assignment.subs.join(", ")}</span>
+            }) }
+        </div>
+    }
+}
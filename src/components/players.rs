@@ -1,19 +1,15 @@
 use gloo_net::http::Request;
 use leptos::task::spawn_local;
-use leptos::{ev::SubmitEvent, prelude::*};
-use regex::Regex;
-use std::sync::LazyLock;
+use leptos::{ev::{DragEvent, SubmitEvent}, prelude::*};
+use std::collections::BTreeMap;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{File, HtmlInputElement};
 
-use crate::teamgen::Player;
+use crate::components::columns::ColumnMapping;
+use crate::sheet::{csv_export_url, file_to_csv, parse_players, sheet_headers, ColumnMap, RowError};
+use crate::teamgen::{Player, team_label};
 use crate::utils::{from_local_storage, set_local_storage};
 
-const SHEET_PARSER: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(
-        r#"(http(s)?:\/\/)?docs\.google\.com\/spreadsheets\/d\/([\w-]*)\/edit\?gid=(\w*)(#)?.*"#,
-    )
-    .unwrap()
-});
-
 #[component]
 pub fn Players(players: RwSignal<Vec<Player>>) -> impl IntoView {
     let player_sheet_url_default = from_local_storage(
@@ -25,45 +21,126 @@ pub fn Players(players: RwSignal<Vec<Player>>) -> impl IntoView {
         set_local_storage("player_sheet_url", player_sheet_url.get());
     });
 
+    // How each sheet's columns are read, by sheet URL.
+    let column_maps = RwSignal::new(from_local_storage("column_maps", BTreeMap::<String, ColumnMap>::new()));
+    Effect::new(move || {
+        set_local_storage("column_maps", column_maps.get());
+    });
+
+    // The last sheet fetched, and the URL it came from.
+    let sheet = RwSignal::new(None::<(String, String)>);
+    let mapping = RwSignal::new(ColumnMap::new());
+    Effect::new(move || {
+        let mapping = mapping.get();
+        if let Some((url, _)) = sheet.get_untracked() {
+            column_maps.update(|maps| { maps.insert(url, mapping); });
+        }
+    });
+
+    // Rows of the last sheet that couldn't be read, so they can be fixed.
+    let row_errors = RwSignal::new(Vec::<RowError>::new());
+
+    // The sheet is read again whenever its columns are mapped differently.
+    Effect::new(move || {
+        if let Some((_, csv)) = sheet.get() {
+            let (parsed, errors) = parse_players(&csv, &mapping.get());
+            players.set(parsed);
+            row_errors.set(errors);
+        }
+    });
+    let headers = Signal::derive(move || {
+        sheet.get()
+            .map(|(_, csv)| sheet_headers(&csv, &mapping.get()))
+            .unwrap_or_default()
+    });
+
+    let player_sheet_tab_default = from_local_storage("player_sheet_tab", String::new());
+    let player_sheet_tab = RwSignal::new(player_sheet_tab_default.clone());
+    Effect::new(move || {
+        set_local_storage("player_sheet_tab", player_sheet_tab.get());
+    });
+
     let update_player_sheet_url = move |ev| {
         let v = event_target_value(&ev);
         player_sheet_url.set(v);
     };
 
+    let update_player_sheet_tab = move |ev| {
+        let v = event_target_value(&ev);
+        player_sheet_tab.set(v);
+    };
+
+    let csv_url = Signal::derive(move || csv_export_url(&player_sheet_url.get(), &player_sheet_tab.get()));
+    // Why the last sheet couldn't be downloaded or opened.
+    let load_error = RwSignal::new(None::<String>);
+
     let players_event = move |ev: SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            let mut player_sheet_url = player_sheet_url.get();
-            if player_sheet_url.is_empty() {
-                player_sheet_url.push_str("");
-            }
-
-            let captures = SHEET_PARSER
-                .captures(&player_sheet_url)
-                .ok_or_else(|| format!("Invalid URL..."))
-                .unwrap();
-
-            let sheet_id = captures.get(3).unwrap().as_str();
-            let gid = captures.get(4).unwrap().as_str();
-
-            let csv_export_url = format!(
-                "https://docs.google.com/spreadsheets/d/{sheet_id}/export?format=csv&gid={gid}"
-            );
+            let player_sheet_url = player_sheet_url.get();
+            let Ok(csv_export_url) = csv_url.get() else {
+                return;
+            };
 
-            let csv_resp = Request::get(&csv_export_url).send().await.unwrap();
-
-            let players_csv = csv_resp.text().await.unwrap();
-            let mut lines = players_csv.split("\n");
+            let players_csv = match Request::get(&csv_export_url).send().await {
+                Ok(resp) if resp.ok() => resp.text().await.map_err(|e| e.to_string()),
+                Ok(resp) => Err(format!(
+                    "The sheet couldn't be downloaded ({}), so check it's shared with anyone with the link",
+                    resp.status(),
+                )),
+                Err(e) => Err(e.to_string()),
+            };
+            let players_csv = match players_csv {
+                Ok(players_csv) => players_csv,
+                Err(e) => {
+                    load_error.set(Some(e));
+                    return;
+                },
+            };
+            load_error.set(None);
+            mapping.set(column_maps.get_untracked().get(&player_sheet_url).cloned().unwrap_or_default());
+            sheet.set(Some((player_sheet_url, players_csv)));
+        });
+    };
 
-            while let Some(l) = lines.next() {
-                if l.starts_with("fName") {
-                    break;
-                }
+    // Roster files are read like a sheet, with their columns mapped by file name.
+    let import_file = move |file: File| {
+        spawn_local(async move {
+            let name = file.name();
+            let bytes = match JsFuture::from(file.array_buffer()).await {
+                Ok(buffer) => js_sys::Uint8Array::new(&buffer).to_vec(),
+                Err(_) => {
+                    load_error.set(Some(format!("{name} couldn't be read")));
+                    return;
+                },
+            };
+            match file_to_csv(&name, &bytes) {
+                Ok(players_csv) => {
+                    load_error.set(None);
+                    mapping.set(column_maps.get_untracked().get(&name).cloned().unwrap_or_default());
+                    sheet.set(Some((name, players_csv)));
+                },
+                Err(e) => load_error.set(Some(e)),
             }
-            players.set(lines.filter_map(|l| Player::from_row(l)).collect());
         });
     };
 
+    let pick_file = move |ev| {
+        let input = event_target::<HtmlInputElement>(&ev);
+        if let Some(file) = input.files().and_then(|files| files.get(0)) {
+            import_file(file);
+        }
+        // Picking the same file again should read it again.
+        input.set_value("");
+    };
+
+    let drop_file = move |ev: DragEvent| {
+        ev.prevent_default();
+        if let Some(file) = ev.data_transfer().and_then(|data| data.files()).and_then(|files| files.get(0)) {
+            import_file(file);
+        }
+    };
+
     view! {
         <div class="col">
             <form class="row" on:submit=players_event>
@@ -73,33 +150,56 @@ pub fn Players(players: RwSignal<Vec<Player>>) -> impl IntoView {
                     on:input=update_player_sheet_url
                     value=player_sheet_url_default
                 />
-                <button type="submit">"Refresh player list"</button>
+                <input
+                    id="team-sheet-tab-input"
+                    placeholder="Tab name (optional)"
+                    on:input=update_player_sheet_tab
+                    value=player_sheet_tab_default
+                />
+                <button type="submit" disabled=move || csv_url.get().is_err()>"Refresh player list"</button>
             </form>
+            { move || (!player_sheet_url.get().trim().is_empty())
+                .then(|| csv_url.get().err())
+                .flatten()
+                .map(|e| view! { <p class="validation-error">{e}</p> }) }
+            <div class="row file-drop" on:dragover=|ev: DragEvent| ev.prevent_default() on:drop=drop_file>
+                <label for="roster-file-input">"Or drop a CSV, Excel, ODS or JSON roster here, or "</label>
+                <input
+                    id="roster-file-input"
+                    type="file"
+                    accept=".csv,.txt,.json,.xlsx,.xlsm,.xlsb,.xls,.ods"
+                    on:change=pick_file
+                />
+            </div>
+            { move || load_error.get().map(|e| view! { <p class="validation-error">{e}</p> }) }
+            { move || (!row_errors.get().is_empty()).then(|| view! {
+                <h3>"Unreadable Rows: ("{ move || row_errors.get().len() }")"</h3>
+                <ul id="row-errors">
+                    { move || row_errors.get().into_iter()
+                        .map(|e| view! { <li>{e.to_string()}</li> })
+                        .collect_view() }
+                </ul>
+            }) }
+            { move || sheet.with(Option::is_some).then(|| view! { <ColumnMapping headers mapping/> }) }
             <h3>" Participating Players: ("{ move || players.get().len() }")"</h3>
             <table id="player-listing">
                 <tr>
-                    <th> Name </th><th> Rating </th><th> Gender </th><th> Team Lock </th><th> Position </th>
+                    <th> Name </th><th> Rating </th><th> Gender </th><th> Team Lock </th><th> Position </th><th> Attributes </th>
                 </tr>
                 { move || players.get().into_iter()
                     .map(|p| view!{ <tr>
                         <td>{p.name}</td>
                         <td>{p.rating}</td>
                         <td>{ move || if p.gender {"F"} else {"M"} }</td>
-                        <td>{ move || {
-                            if let Some(fixed) = p.fixed_team {
-                                if fixed {
-                                    "A"
-                                } else {
-                                    "B"
-                                }
-                            } else {
-                                ""
-                            }
-                        } }</td>
+                        <td>{ p.fixed_team.map(team_label).unwrap_or_default() }</td>
                         <td>{ move || match &p.position {
                             Some(arr) => serde_json::to_string(arr).unwrap(),
                             None => "".to_string(),
                         }}</td>
+                        <td>{ p.attributes.iter()
+                            .map(|(attribute, rating)| format!("{attribute} {rating}"))
+                            .collect::<Vec<_>>()
+                            .join(", ") }</td>
                     </tr> })
                     .collect_view() }
             </table>
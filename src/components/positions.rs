@@ -0,0 +1,72 @@
+use leptos::{ev::SubmitEvent, prelude::*};
+
+use crate::positions::{Position, PositionCatalog};
+
+/// Splits a comma separated list of aliases, dropping empty ones.
+fn parse_aliases(aliases: &str) -> Vec<String> {
+    aliases.split(',')
+        .map(|a| a.trim().to_lowercase())
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// Lists and edits the positions players can list, and the other names each goes by.
+#[component]
+pub fn Positions(positions: RwSignal<PositionCatalog>) -> impl IntoView {
+    let name = RwSignal::new(String::new());
+    let aliases = RwSignal::new(String::new());
+
+    let add_position = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let new_name = name.get().trim().to_lowercase();
+        if new_name.is_empty() {
+            return;
+        }
+        let position = Position { name: new_name, aliases: parse_aliases(&aliases.get()) };
+        positions.update(|catalog| {
+            // A name the catalog already knows, even as an alias, would be ambiguous.
+            if catalog.normalize(&position.name) == position.name
+                && !catalog.positions.iter().any(|p| p.name == position.name)
+            {
+                catalog.positions.push(position);
+            }
+        });
+        name.set(String::new());
+        aliases.set(String::new());
+    };
+
+    view! {
+        <h3>"Positions"</h3>
+        <table id="position-catalog">
+            <tr>
+                <th> Position </th><th> Also Known As </th><th></th>
+            </tr>
+            { move || positions.get().positions.into_iter()
+                .enumerate()
+                .map(|(i, p)| view! { <tr>
+                    <td>{p.name.to_uppercase()}</td>
+                    <td>
+                        <input
+                            value=p.aliases.join(", ")
+                            on:change=move |ev| {
+                                let v = event_target_value(&ev);
+                                positions.update(|catalog| catalog.positions[i].aliases = parse_aliases(&v));
+                            }
+                        />
+                    </td>
+                    <td>
+                        <button on:click=move |_| positions.update(|catalog| { catalog.positions.remove(i); })>
+                            "Remove"
+                        </button>
+                    </td>
+                </tr> })
+                .collect_view() }
+        </table>
+        <form class="row" on:submit=add_position>
+            <input placeholder="Position" prop:value=name on:input=move |ev| name.set(event_target_value(&ev))/>
+            <input placeholder="Aliases, like cb, def" prop:value=aliases on:input=move |ev| aliases.set(event_target_value(&ev))/>
+            <button type="submit">"Add Position"</button>
+            <button type="button" on:click=move |_| positions.set(PositionCatalog::default())>"Reset Positions"</button>
+        </form>
+    }
+}
@@ -0,0 +1,102 @@
+use leptos::{ev::SubmitEvent, prelude::*};
+
+use crate::ratings::{learn_ratings, MatchResult};
+use crate::teamgen::{team_label, Player};
+
+/// Records the score of the current teams' game, and lists past results with the ratings learned from them.
+#[component]
+pub fn Results(
+    players: RwSignal<Vec<Player>>,
+    matches: RwSignal<Vec<MatchResult>>,
+    teams: RwSignal<Vec<Vec<Player>>>,
+    shared: RwSignal<Option<Player>>,
+) -> impl IntoView {
+    let scores = RwSignal::new(Vec::<u32>::new());
+    Effect::new(move || scores.set(vec![0; teams.get().len()]));
+
+    let record_result = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let teams = teams.get();
+        if teams.is_empty() {
+            return;
+        }
+        let result = MatchResult {
+            played: String::from(js_sys::Date::new_0().to_iso_string()),
+            teams: teams.iter()
+                .map(|team| team.iter().map(|p| p.name.clone()).collect())
+                .collect(),
+            shared: shared.get().map(|p| p.name),
+            scores: scores.get(),
+        };
+        matches.update(|matches| matches.push(result));
+    };
+
+    let history = Signal::derive(move || learn_ratings(&players.get(), &matches.get()));
+
+    view! {
+        <div class="col">
+            { move || (!teams.get().is_empty()).then(|| view! {
+                <form class="row" on:submit=record_result>
+                    { (0..teams.get().len())
+                        .map(|t| view! {
+                            <label class="team-delta-label">"Team "{team_label(t)}":"</label>
+                            <input
+                                type="number"
+                                min="0"
+                                value=0
+                                on:input=move |ev| {
+                                    let v = event_target_value(&ev);
+                                    scores.update(|scores| scores[t] = v.parse().unwrap_or(0));
+                                }
+                                class="team-delta-input"
+                            />
+                        })
+                        .collect_view() }
+                    <button type="submit">"Record Result"</button>
+                </form>
+            }) }
+            <h3>"Match History"</h3>
+            <table id="match-history">
+                { move || matches.get().into_iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, result)| view! { <tr>
+                        // Just the date of the timestamp.
+                        <td>{result.played.chars().take(10).collect::<String>()}</td>
+                        <td>{ result.scores.iter().map(u32::to_string).collect::<Vec<_>>().join(" - ") }</td>
+                        <td>{ result.teams.iter()
+                            .map(|team| team.join(", "))
+                            .collect::<Vec<_>>()
+                            .join(" vs ") }</td>
+                        <td>
+                            <button on:click=move |_| matches.update(|matches| { matches.remove(i); })>
+                                "Remove"
+                            </button>
+                        </td>
+                    </tr> })
+                    .collect_view() }
+            </table>
+            <h3>"Learned Ratings"</h3>
+            <table id="learned-ratings">
+                <tr>
+                    <th> Name </th><th> Manual </th><th> Learned </th><th> Games </th><th> Times Shared </th>
+                </tr>
+                { move || {
+                    let history = history.get();
+                    players.get().into_iter()
+                        .map(|p| {
+                            let learned = history.get(&p.name);
+                            view! { <tr>
+                                <td>{p.name.clone()}</td>
+                                <td>{p.rating}</td>
+                                <td>{ learned.and_then(|h| h.last()).map(|r| format!("{r:.2}")).unwrap_or_default() }</td>
+                                <td>{ learned.map_or(0, Vec::len) }</td>
+                                <td>{ matches.get().iter().filter(|m| m.shared.as_ref() == Some(&p.name)).count() }</td>
+                            </tr> }
+                        })
+                        .collect_view()
+                } }
+            </table>
+        </div>
+    }
+}
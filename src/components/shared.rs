@@ -0,0 +1,106 @@
+use leptos::prelude::*;
+
+use crate::positions::PositionCatalog;
+use crate::teamgen::{Player, SharedRules};
+
+/// Edits the rules for who may split their time between the teams.
+#[component]
+pub fn SharedPlayer(
+    players: RwSignal<Vec<Player>>,
+    positions: RwSignal<PositionCatalog>,
+    rules: RwSignal<SharedRules>,
+) -> impl IntoView {
+    let excluded = RwSignal::new(String::new());
+
+    let update_min_rating = move |ev| {
+        let v = event_target_value(&ev);
+        rules.update(|rules| rules.min_rating = v.parse().ok());
+    };
+
+    let update_max_rating = move |ev| {
+        let v = event_target_value(&ev);
+        rules.update(|rules| rules.max_rating = v.parse().ok());
+    };
+
+    let exclude_player = move |_| {
+        let name = excluded.get();
+        rules.update(|rules| {
+            if !name.is_empty() && !rules.excluded_players.contains(&name) {
+                rules.excluded_players.push(name);
+            }
+        });
+    };
+
+    view! {
+        <h3>"Shared Player"</h3>
+        <div class="row">
+            <span class="team-delta-label">"Can't Play:"</span>
+            { move || positions.get().positions.into_iter()
+                .map(|position| {
+                    let name = position.name;
+                    let id = format!("shared-{name}-input");
+                    let checked = rules.get_untracked().excluded_positions.contains(&name);
+                    let label = name.to_uppercase();
+                    let update = move |ev| {
+                        let exclude = event_target_checked(&ev);
+                        rules.update(|rules| {
+                            rules.excluded_positions.retain(|p| *p != name);
+                            if exclude {
+                                rules.excluded_positions.push(name.clone());
+                            }
+                        });
+                    };
+                    view! {
+                        <input id=id.clone() type="checkbox" checked=checked on:change=update/>
+                        <label for=id>{label}</label>
+                    }
+                })
+                .collect_view() }
+        </div>
+        <div class="row">
+            <label for="shared-min-rating-input" class="team-delta-label">"Rating Between:"</label>
+            <input
+                id="shared-min-rating-input"
+                type="number"
+                step="0.5"
+                placeholder="any"
+                value=rules.get_untracked().min_rating
+                on:input=update_min_rating
+                class="team-delta-input"
+            />
+            <label for="shared-max-rating-input" class="team-delta-label">"And:"</label>
+            <input
+                id="shared-max-rating-input"
+                type="number"
+                step="0.5"
+                placeholder="any"
+                value=rules.get_untracked().max_rating
+                on:input=update_max_rating
+                class="team-delta-input"
+            />
+        </div>
+        <div class="row">
+            <select on:change=move |ev| excluded.set(event_target_value(&ev))>
+                <option value="">"Player..."</option>
+                { move || players.get().into_iter()
+                    .map(|p| view! { <option value=p.name.clone()>{p.name.clone()}</option> })
+                    .collect_view() }
+            </select>
+            <button on:click=exclude_player>"Never Share"</button>
+        </div>
+        <table id="shared-exclusions">
+            { move || rules.get().excluded_players.into_iter()
+                .enumerate()
+                .map(|(i, name)| view! { <tr>
+                    <td>{name}</td>
+                    <td>"is never shared"</td>
+                    <td>
+                        <button on:click=move |_| rules.update(|rules| { rules.excluded_players.remove(i); })>
+                            "Remove"
+                        </button>
+                    </td>
+                </tr> })
+                .collect_view() }
+        </table>
+    }
+}
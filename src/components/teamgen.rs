@@ -1,13 +1,26 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use leptos::{ev::SubmitEvent, prelude::*};
 use thaw::ColorPicker;
 use thaw::{Toast, ToastBody, ToastTitle, ToasterInjection};
 use thaw::Color;
 use palette::Srgb;
+use web_time::Duration;
 
-use crate::teamgen::{Player, get_even_teams};
+use crate::components::adjust::Adjust;
+use crate::components::draft::Draft;
+use crate::components::pairings::Pairings;
+use crate::components::pitch::Pitch;
+use crate::components::positions::Positions;
+use crate::components::results::Results;
+use crate::components::shared::SharedPlayer;
+use crate::formation::parse_formation;
+use crate::positions::PositionCatalog;
+use crate::ratings::{learn_ratings, MatchResult, RatingSource};
+use crate::rotation::plan_rotation;
+use crate::teamgen::{attribute_total, Constraints, GeneratedTeams, OddRoster, Pairing, Player, SharedRules, rating_spread, shared_label, team_label};
 use crate::utils::*;
+use crate::worker::{Generation, Job, Request, Response};
 
 /// Generates a rw signal that persists changes to local storage, and will load that as the default on page refresh.
 macro_rules! local_storage_signal {
@@ -18,6 +31,22 @@ macro_rules! local_storage_signal {
     };
 }
 
+/// The most teams the generator can be asked for.
+const MAX_TEAMS: usize = 6;
+
+/// How many recent games the generator looks back over to keep teams varied.
+const PAST_SESSIONS: usize = 5;
+
+/// Colors used for each team until the user picks their own.
+const DEFAULT_TEAM_COLORS: [(f32, f32, f32); MAX_TEAMS] = [
+    (1.0, 0.48, 0.0),
+    (0.0, 0.48, 1.0),
+    (0.13, 0.65, 0.3),
+    (0.6, 0.2, 0.75),
+    (0.9, 0.15, 0.2),
+    (0.95, 0.85, 0.2),
+];
+
 fn get_color_code(c: Color) -> String {
     let c: Srgb<u8> = match c {
         Color::RGB(c) => c,
@@ -37,77 +66,282 @@ pub fn TeamGenerator(players: RwSignal<Vec<Player>>) -> impl IntoView {
     let toaster = ToasterInjection::expect_context();
 
     // Team signals
-    let team_a = RwSignal::new(vec![]);
-    let team_b = RwSignal::new(vec![]);
+    let teams = RwSignal::new(Vec::<Vec<Player>>::new());
+    let team_gap = RwSignal::new(None::<f32>);
+    let team_seed = RwSignal::new(None::<u64>);
+    let team_shared = RwSignal::new(None::<Player>);
+
+    local_storage_signal!(odd_roster, odd_roster_default, OddRoster::Shared);
+
+    // The teams as listed, with the shared player on every team for their part of the game.
+    let listed_teams = Signal::derive(move || {
+        let mut teams = teams.get();
+        let num_teams = teams.len();
+        if let Some(shared) = team_shared.get() {
+            for (t, team) in teams.iter_mut().enumerate() {
+                let label = match odd_roster.get() {
+                    OddRoster::Substitute => "sub".to_string(),
+                    _ => shared_label(t, num_teams),
+                };
+                team.push(Player {
+                    name: format!("{} ({label})", shared.name),
+                    ..shared.clone()
+                });
+            }
+        }
+        teams
+    });
+
+    // Every split offered by the last run, and which one is being shown.
+    let candidates = RwSignal::new(Vec::<GeneratedTeams>::new());
+    let candidate = RwSignal::new(0);
+    let show_candidate = move |i: usize| {
+        if let Some(c) = candidates.get().get(i) {
+            candidate.set(i);
+            teams.set(c.teams.clone());
+            team_shared.set(c.shared.clone());
+            team_gap.set(Some(c.delta));
+        }
+    };
 
-    let team_a_color_default = from_local_storage("team_a_color", RGB{red: 255.0, green: 123.0, blue: 0.0});
-    let team_a_color = RwSignal::new(Color::from(team_a_color_default.clone().srgb()));
-    Effect::new(move || {set_local_storage_color("team_a_color", team_a_color.get());});
+    // The run in progress, if any, and how far along it is.
+    let generation = StoredValue::new_local(None::<Generation>);
+    let progress = RwSignal::new(None::<f32>);
 
-    let team_b_color_default = from_local_storage("team_b_color", RGB{red: 0.0, green: 123.0, blue: 255.0});
-    let team_b_color = RwSignal::new(Color::from(team_b_color_default.clone().srgb()));
-    Effect::new(move || {set_local_storage_color("team_b_color", team_b_color.get());});
+    // Left blank to pick a fresh seed, not persisted so every visit starts random.
+    let seed_input = RwSignal::new(String::new());
+
+    let team_colors: [RwSignal<Color>; MAX_TEAMS] = std::array::from_fn(|t| {
+        let key = format!("team_{}_color", team_label(t).to_lowercase());
+        let (red, green, blue) = DEFAULT_TEAM_COLORS[t];
+        let default = from_local_storage(&key, RGB{red, green, blue});
+        let color = RwSignal::new(Color::from(default.srgb()));
+        Effect::new(move || {set_local_storage_color(&key, color.get());});
+        color
+    });
     
+    local_storage_signal!(num_teams, num_teams_default, 2);
+    local_storage_signal!(candidate_count, candidate_count_default, 5);
     local_storage_signal!(team_delta, team_delta_default, 1.0);
-    local_storage_signal!(min_defense, min_defense_default, 1);
-    local_storage_signal!(min_forward, min_forward_default, 1);
-    local_storage_signal!(min_gk, min_gk_default, 1);
-    local_storage_signal!(min_midfield, min_midfield_default, 1);
+    local_storage_signal!(positions, positions_default, PositionCatalog::default());
+    local_storage_signal!(min_positions, min_positions_default, PositionCatalog::default().positions.into_iter()
+        .map(|p| (p.name, 1))
+        .collect::<BTreeMap<String, usize>>());
+    local_storage_signal!(max_positions, max_positions_default, BTreeMap::<String, usize>::new());
+    local_storage_signal!(even_positions, even_positions_default, false);
+    local_storage_signal!(max_gender_delta, max_gender_delta_default, None::<usize>);
+    local_storage_signal!(min_per_gender, min_per_gender_default, 0);
+    local_storage_signal!(periods, periods_default, 2);
+    local_storage_signal!(on_field, on_field_default, None::<usize>);
+    local_storage_signal!(formation, formation_default, String::new());
+    local_storage_signal!(pairings, pairings_default, Vec::<Pairing>::new());
+    local_storage_signal!(matches, matches_default, Vec::<MatchResult>::new());
+    local_storage_signal!(rating_source, rating_source_default, RatingSource::Manual);
+    local_storage_signal!(repeat_penalty, repeat_penalty_default, 0.0);
+    local_storage_signal!(min_changes, min_changes_default, 0);
+    local_storage_signal!(depth_weight, depth_weight_default, 0.0);
+    local_storage_signal!(top_split, top_split_default, 0);
+    local_storage_signal!(time_budget, time_budget_default, 10.0);
+    local_storage_signal!(shared_rules, shared_rules_default, SharedRules::default());
+    local_storage_signal!(shared_repeat_penalty, shared_repeat_penalty_default, 0.5);
+    local_storage_signal!(attribute_tolerances, attribute_tolerances_default, BTreeMap::<String, f32>::new());
+
+    // Every extra rating listed for someone on the roster.
+    let attributes = Signal::derive(move || players.get().iter()
+        .flat_map(|p| p.attributes.keys().cloned())
+        .collect::<BTreeSet<_>>());
+
+    let update_num_teams = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse::<usize>() {
+            num_teams.set(number.clamp(2, MAX_TEAMS));
+        }
+    };
+
+    let update_candidate_count = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse::<usize>() {
+            candidate_count.set(number.max(1));
+        }
+    };
 
     let update_max_team_delta = move |ev| {
         let v = event_target_value(&ev);
-        let number = v.parse().unwrap();
-        team_delta.set(number); 
+        if let Ok(number) = v.parse() {
+            team_delta.set(number);
+        }
     };
     
 
-    let update_min_defense = move |ev| {
+    let update_even_positions = move |ev| {
+        even_positions.set(event_target_checked(&ev));
+    };
+
+    let update_max_gender_delta = move |ev| {
         let v = event_target_value(&ev);
-        let number = v.parse().unwrap();
-        min_defense.set(number);
+        // Left blank when any gender split is fine.
+        max_gender_delta.set(v.parse().ok());
     };
 
-    let update_min_forward = move |ev| {
+    let update_min_per_gender = move |ev| {
         let v = event_target_value(&ev);
-        let number = v.parse().unwrap();
-        min_forward.set(number);
+        if let Ok(number) = v.parse() {
+            min_per_gender.set(number);
+        }
     };
 
-    let update_min_gk = move |ev| {
+    let update_periods = move |ev| {
         let v = event_target_value(&ev);
-        let number = v.parse().unwrap();
-        min_gk.set(number);
+        if let Ok(number) = v.parse::<usize>() {
+            periods.set(number.max(1));
+        }
     };
 
-    let update_min_midfield = move |ev| {
+    let update_on_field = move |ev| {
         let v = event_target_value(&ev);
-        let number = v.parse().unwrap();
-        min_midfield.set(number);
+        // Left blank when everyone plays the whole game.
+        on_field.set(v.parse().ok());
     };
 
-    let team_gen_event = move |ev: SubmitEvent| {
-        ev.prevent_default();
-        let players = players.get();
-        let mut pos = BTreeMap::new();
-
-        pos.insert("gk".to_string(), min_gk.get());
-        pos.insert("df".to_string(), min_defense.get());
-        pos.insert("mid".to_string(), min_midfield.get());
-        pos.insert("fw".to_string(), min_forward.get());
-        
-        match get_even_teams(&players, team_delta.get(), &pos) {
-            Ok((mut a, mut b)) => {
-                a.sort_by(|x, y| x.name.cmp(&y.name));
-                b.sort_by(|x, y| x.name.cmp(&y.name));
-                team_a.set(a);
-                team_b.set(b);
+    let rotation = Signal::derive(move || plan_rotation(
+        &teams.get(),
+        team_shared.get().as_ref(),
+        odd_roster.get() == OddRoster::Substitute,
+        on_field.get(),
+        periods.get(),
+    ));
+
+    let update_formation = move |ev| {
+        formation.set(event_target_value(&ev));
+    };
+
+    // Left blank to skip laying the teams out on a pitch.
+    let parsed_formation = Signal::derive(move || {
+        let formation = formation.get();
+        (!formation.trim().is_empty()).then(|| parse_formation(&formation, &positions.get()))
+    });
+
+    let update_seed = move |ev| {
+        seed_input.set(event_target_value(&ev));
+    };
+
+    let update_repeat_penalty = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse() {
+            repeat_penalty.set(number);
+        }
+    };
+
+    let update_min_changes = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse() {
+            min_changes.set(number);
+        }
+    };
+
+    let update_depth_weight = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse() {
+            depth_weight.set(number);
+        }
+    };
+
+    let update_top_split = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse() {
+            top_split.set(number);
+        }
+    };
+
+    let update_time_budget = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse::<f32>() {
+            time_budget.set(number.max(0.1));
+        }
+    };
+
+    let update_odd_roster = move |ev| {
+        let v = event_target_value(&ev);
+        odd_roster.set(match v.as_str() {
+            // The smaller team starts out needing half an average player more than the larger one.
+            "uneven" => {
+                let players = players.get();
+                OddRoster::Uneven(players.iter().map(|p| p.rating).sum::<f32>() / players.len().max(1) as f32 / 2.0)
+            },
+            "substitute" => OddRoster::Substitute,
+            _ => OddRoster::Shared,
+        });
+    };
+
+    let update_uneven_compensation = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse::<f32>() {
+            odd_roster.set(OddRoster::Uneven(number.max(0.0)));
+        }
+    };
+
+    let update_shared_repeat_penalty = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(number) = v.parse() {
+            shared_repeat_penalty.set(number);
+        }
+    };
+
+    let update_rating_source = move |ev| {
+        let v = event_target_value(&ev);
+        rating_source.set(match v.as_str() {
+            "learned" => RatingSource::Learned,
+            "blend" => RatingSource::Blend(0.5),
+            _ => RatingSource::Manual,
+        });
+    };
+
+    let update_blend_share = move |ev| {
+        let v = event_target_value(&ev);
+        if let Ok(percent) = v.parse::<f32>() {
+            rating_source.set(RatingSource::Blend(percent.clamp(0.0, 100.0) / 100.0));
+        }
+    };
+
+    let show_generated = move |result: Result<Vec<GeneratedTeams>, String>| {
+        match result {
+            Ok(mut generated) => {
+                for team in generated.iter_mut().flat_map(|c| c.teams.iter_mut()) {
+                    team.sort_by(|x, y| x.name.cmp(&y.name));
+                }
+                let GeneratedTeams { delta, constraints_met, seed, complete, .. } = generated[0];
+                let warnings = generated[0].warnings.join("\n");
+                candidates.set(generated);
+                show_candidate(0);
+                team_seed.set(Some(seed));
+                if !complete {
+                    toaster.dispatch_toast(move || view! {
+                        <Toast>
+                            <ToastTitle>"Search Ran Out Of Time"</ToastTitle>
+                            <ToastBody>"These are the best teams found within the time budget. Raise it for a fuller search."</ToastBody>
+                        </Toast>
+                    }, Default::default());
+                }
+                if !constraints_met {
+                    toaster.dispatch_toast(move || view! {
+                        <Toast>
+                            <ToastTitle>"Showing The Closest Teams Found"</ToastTitle>
+                            <ToastBody>
+                                {format!("These teams are {delta} apart and may not meet every position, gender and pairing rule.")}
+                                // Each reason is on its own line.
+                                <div style="white-space: pre-line">{warnings}</div>
+                            </ToastBody>
+                        </Toast>
+                    }, Default::default());
+                }
             },
             Err(e) => {
                 toaster.dispatch_toast(move || view! {
                     <Toast>
                         <ToastTitle>"Failed To Generate Equal Teams"</ToastTitle>
                         <ToastBody>
-                            {e}
+                            // Each reason is on its own line.
+                            <div style="white-space: pre-line">{e}</div>
                         </ToastBody>
                     </Toast>
                 }, Default::default());
@@ -115,13 +349,147 @@ pub fn TeamGenerator(players: RwSignal<Vec<Player>>) -> impl IntoView {
         };
     };
 
+    // The roster with the chosen rating source applied, as the generator sees it.
+    let rated_players = Signal::derive(move || {
+        let history = learn_ratings(&players.get(), &matches.get());
+        let source = rating_source.get();
+        players.get().into_iter()
+            .map(|p| Player { rating: source.rating(&p, &history), ..p })
+            .collect::<Vec<_>>()
+    });
+
+    let constraints = Signal::derive(move || {
+        // Limits for positions taken out of the catalog no longer apply.
+        let positions = positions.get();
+        let in_catalog = |(pos, _): &(String, usize)| positions.positions.iter().any(|p| &p.name == pos);
+        Constraints {
+            max_delta: team_delta.get(),
+            min_positions: min_positions.get().into_iter().filter(in_catalog).collect(),
+            max_positions: max_positions.get().into_iter().filter(in_catalog).collect(),
+            even_positions: even_positions.get(),
+            max_gender_delta: max_gender_delta.get(),
+            min_per_gender: min_per_gender.get(),
+            pairings: pairings.get(),
+            attribute_tolerances: attribute_tolerances.get().into_iter()
+                .filter(|(attribute, _)| attributes.get().contains(attribute))
+                .collect(),
+            past_teams: matches.get().iter()
+                .rev()
+                .take(PAST_SESSIONS)
+                .map(MatchResult::team_of)
+                .collect(),
+            repeat_penalty: repeat_penalty.get(),
+            min_changes: min_changes.get(),
+            depth_weight: depth_weight.get(),
+            top_split: top_split.get(),
+            positions,
+            shared_rules: shared_rules.get(),
+            past_shared: matches.get().iter()
+                .filter_map(|m| m.shared.clone())
+                .fold(BTreeMap::new(), |mut counts, name| {
+                    *counts.entry(name).or_insert(0) += 1;
+                    counts
+                }),
+            shared_repeat_penalty: shared_repeat_penalty.get(),
+            odd_roster: odd_roster.get(),
+        }
+    });
+
+    let team_gen_event = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let players = rated_players.get();
+
+        let seed = seed_input.get();
+        let seed = seed.trim();
+        let seed = if seed.is_empty() {
+            None
+        } else {
+            match seed.parse::<u64>() {
+                Ok(seed) => Some(seed),
+                Err(_) => {
+                    toaster.dispatch_toast(move || view! {
+                        <Toast>
+                            <ToastTitle>"Invalid Seed"</ToastTitle>
+                            <ToastBody>
+                                "The seed must be a whole number, or left blank for a random one."
+                            </ToastBody>
+                        </Toast>
+                    }, Default::default());
+                    return;
+                }
+            }
+        };
+
+        let request = Request {
+            players,
+            num_teams: num_teams.get(),
+            constraints: constraints.get(),
+            count: candidate_count.get(),
+            seed,
+            budget: Duration::from_secs_f32(time_budget.get()),
+        };
+        let on_response = move |response| match response {
+            Response::Progress(done) => progress.set(Some(done)),
+            Response::Done(result) => {
+                progress.set(None);
+                show_generated(result);
+            },
+            Response::Ready | Response::Adjusted(_) => {},
+        };
+        // Starting a new run stops the last one.
+        generation.set_value(None);
+        match Generation::start(Job::Generate(request), on_response) {
+            Ok(started) => {
+                generation.set_value(Some(started));
+                progress.set(Some(0.0));
+            },
+            Err(e) => {
+                toaster.dispatch_toast(move || view! {
+                    <Toast>
+                        <ToastTitle>"Failed To Start Generating"</ToastTitle>
+                        <ToastBody>{format!("{e:?}")}</ToastBody>
+                    </Toast>
+                }, Default::default());
+            }
+        }
+    };
+
+    let cancel_generation = move |_| {
+        generation.set_value(None);
+        progress.set(None);
+    };
+
     view! {
         <div id="teams">
             // <ConfigProvider>
-                <ColorPicker value=team_a_color/>
-                <ColorPicker value=team_b_color/>
+                { move || team_colors[..num_teams.get()].iter()
+                    .map(|&color| view! { <ColorPicker value=color/> })
+                    .collect_view() }
             // </ConfigProvider>
             <form class="col" on:submit=team_gen_event>
+                <div class="row">
+                <label for="num-teams-input" class="team-delta-label">Number of Teams:</label>
+                <input
+                    id="num-teams-input"
+                    type="number"
+                    min="2"
+                    max=MAX_TEAMS
+                    value=num_teams_default
+                    on:input=update_num_teams
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="candidate-count-input" class="team-delta-label">Options To Choose From:</label>
+                <input
+                    id="candidate-count-input"
+                    type="number"
+                    min="1"
+                    value=candidate_count_default
+                    on:input=update_candidate_count
+                    class="team-delta-input"
+                />
+                </div>
                 <div class="row">
                 <label for="team-delta-input" class="team-delta-label">Max Team Strength Delta:</label>
                 <input
@@ -133,74 +501,434 @@ pub fn TeamGenerator(players: RwSignal<Vec<Player>>) -> impl IntoView {
                 />
                 </div>
                 <div class="row">
-                <label for="min-fwd-input" class="team-delta-label">Min Forwards:</label>
+                <label for="rating-source-input" class="team-delta-label">Ratings:</label>
+                <select id="rating-source-input" on:change=update_rating_source>
+                    <option value="manual" selected=rating_source_default == RatingSource::Manual>"Manual"</option>
+                    <option value="learned" selected=rating_source_default == RatingSource::Learned>"Learned"</option>
+                    <option value="blend" selected=matches!(rating_source_default, RatingSource::Blend(_))>"Blend"</option>
+                </select>
+                { move || match rating_source.get() {
+                    RatingSource::Blend(share) => Some(view! {
+                        <input
+                            id="blend-share-input"
+                            type="number"
+                            min="0"
+                            max="100"
+                            value=share * 100.0
+                            on:change=update_blend_share
+                            class="team-delta-input"
+                        />
+                        <label for="blend-share-input" class="team-delta-label">"% Learned"</label>
+                    }),
+                    _ => None,
+                } }
+                </div>
+                <div class="row">
+                <label for="odd-roster-input" class="team-delta-label">Odd Roster:</label>
+                <select id="odd-roster-input" on:change=update_odd_roster>
+                    <option value="shared" selected=odd_roster_default == OddRoster::Shared>"Shared Half Player"</option>
+                    <option value="uneven" selected=matches!(odd_roster_default, OddRoster::Uneven(_))>"Uneven Teams"</option>
+                    <option value="substitute" selected=odd_roster_default == OddRoster::Substitute>"Rotating Substitute"</option>
+                </select>
+                { move || match odd_roster.get() {
+                    OddRoster::Uneven(per_player) => Some(view! {
+                        <input
+                            id="uneven-compensation-input"
+                            type="number"
+                            min="0"
+                            step="0.5"
+                            value=per_player
+                            on:change=update_uneven_compensation
+                            class="team-delta-input"
+                        />
+                        <label for="uneven-compensation-input" class="team-delta-label">"Rating The Smaller Team Needs Per Player Short"</label>
+                    }),
+                    _ => None,
+                } }
+                </div>
+                { move || positions.get().positions.into_iter()
+                    .map(|position| {
+                        let name = position.name;
+                        let min_id = format!("min-{name}-input");
+                        let max_id = format!("max-{name}-input");
+                        let min = min_positions.get_untracked().get(&name).copied().unwrap_or(0);
+                        let max = max_positions.get_untracked().get(&name).copied();
+                        let label = format!("Min {}:", name.to_uppercase());
+                        let min_name = name.clone();
+                        let update_min = move |ev| {
+                            let v = event_target_value(&ev);
+                            let number = v.parse().unwrap_or(0);
+                            min_positions.update(|limits| { limits.insert(min_name.clone(), number); });
+                        };
+                        // The position maximums are left blank when there's no limit.
+                        let update_max = move |ev| {
+                            let v = event_target_value(&ev);
+                            max_positions.update(|limits| match v.parse() {
+                                Ok(max) => { limits.insert(name.clone(), max); },
+                                Err(_) => { limits.remove(&name); },
+                            });
+                        };
+                        view! {
+                            <div class="row">
+                            <label for=min_id.clone() class="team-delta-label">{label}</label>
+                            <input
+                                id=min_id
+                                type="number"
+                                value=min
+                                on:input=update_min
+                                class="team-delta-input"
+                            />
+                            <label for=max_id.clone() class="team-delta-label">Max:</label>
+                            <input
+                                id=max_id
+                                type="number"
+                                placeholder="any"
+                                value=max
+                                on:input=update_max
+                                class="team-delta-input"
+                            />
+                            </div>
+                        }
+                    })
+                    .collect_view() }
+                <div class="row">
+                <label for="even-positions-input" class="team-delta-label">Spread Positions Evenly:</label>
+                <input
+                    id="even-positions-input"
+                    type="checkbox"
+                    checked=even_positions_default
+                    on:change=update_even_positions
+                />
+                </div>
+                <div class="row">
+                <label for="max-gender-delta-input" class="team-delta-label">Max Gender Difference:</label>
+                <input
+                    id="max-gender-delta-input"
+                    type="number"
+                    min="0"
+                    placeholder="any"
+                    value=max_gender_delta_default
+                    on:input=update_max_gender_delta
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="min-per-gender-input" class="team-delta-label">Mixed League Min Per Gender:</label>
+                <input
+                    id="min-per-gender-input"
+                    type="number"
+                    min="0"
+                    value=min_per_gender_default
+                    on:input=update_min_per_gender
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="repeat-penalty-input" class="team-delta-label">Repeat Teammate Penalty:</label>
+                <input
+                    id="repeat-penalty-input"
+                    type="number"
+                    min="0"
+                    step="0.1"
+                    value=repeat_penalty_default
+                    on:input=update_repeat_penalty
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="min-changes-input" class="team-delta-label">Min Changes From Last Game:</label>
+                <input
+                    id="min-changes-input"
+                    type="number"
+                    min="0"
+                    value=min_changes_default
+                    on:input=update_min_changes
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="shared-repeat-penalty-input" class="team-delta-label">Repeat Shared Player Penalty:</label>
+                <input
+                    id="shared-repeat-penalty-input"
+                    type="number"
+                    min="0"
+                    step="0.1"
+                    value=shared_repeat_penalty_default
+                    on:input=update_shared_repeat_penalty
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="depth-weight-input" class="team-delta-label">Depth Balance Weight:</label>
                 <input
-                    id="min-fwd-input"
+                    id="depth-weight-input"
                     type="number"
-                    value=min_forward_default
-                    on:input=update_min_forward
+                    min="0"
+                    step="0.1"
+                    value=depth_weight_default
+                    on:input=update_depth_weight
                     class="team-delta-input"
                 />
                 </div>
                 <div class="row">
-                <label for="min-mid-input" class="team-delta-label">Min Midfield:</label>
+                <label for="top-split-input" class="team-delta-label">Split Top Players:</label>
                 <input
-                    id="min-mid-input"
+                    id="top-split-input"
                     type="number"
-                    value=min_midfield_default
-                    on:input=update_min_midfield
+                    min="0"
+                    value=top_split_default
+                    on:input=update_top_split
                     class="team-delta-input"
                 />
                 </div>
+                { move || attributes.get().into_iter()
+                    .map(|attribute| {
+                        let id = format!("max-{attribute}-delta-input");
+                        let value = attribute_tolerances.get_untracked().get(&attribute).copied();
+                        let label = format!("Max {attribute} Delta:");
+                        let update = move |ev| {
+                            let v = event_target_value(&ev);
+                            // Left blank when the attribute doesn't need balancing.
+                            attribute_tolerances.update(|tolerances| match v.parse() {
+                                Ok(tolerance) => { tolerances.insert(attribute.clone(), tolerance); },
+                                Err(_) => { tolerances.remove(&attribute); },
+                            });
+                        };
+                        view! {
+                            <div class="row">
+                            <label for=id.clone() class="team-delta-label">{label}</label>
+                            <input
+                                id=id
+                                type="number"
+                                placeholder="any"
+                                value=value
+                                on:input=update
+                                class="team-delta-input"
+                            />
+                            </div>
+                        }
+                    })
+                    .collect_view() }
                 <div class="row">
-                <label for="min-d-input" class="team-delta-label">Min Defense:</label>
+                <label for="periods-input" class="team-delta-label">Periods:</label>
                 <input
-                    id="min-d-input"
+                    id="periods-input"
                     type="number"
-                    value=min_defense_default
-                    on:input=update_min_defense
+                    min="1"
+                    value=periods_default
+                    on:input=update_periods
                     class="team-delta-input"
                 />
                 </div>
                 <div class="row">
-                <label for="min-gk-input" class="team-delta-label">Min GK:</label>
+                <label for="on-field-input" class="team-delta-label">Players On Field:</label>
                 <input
-                    id="min-gk-input"
+                    id="on-field-input"
                     type="number"
-                    value=min_gk_default
-                    on:input=update_min_gk
+                    min="1"
+                    placeholder="everyone"
+                    value=on_field_default
+                    on:input=update_on_field
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="formation-input" class="team-delta-label">Formation:</label>
+                <input
+                    id="formation-input"
+                    placeholder="e.g. 4-4-2 or 3 df-5-2"
+                    prop:value=formation
+                    on:input=update_formation
+                    class="team-delta-input"
+                />
+                { move || match parsed_formation.get() {
+                    Some(Err(e)) => Some(view! { <span class="team-delta-label">{e}</span> }),
+                    _ => None,
+                } }
+                </div>
+                <div class="row">
+                <label for="seed-input" class="team-delta-label">Seed:</label>
+                <input
+                    id="seed-input"
+                    placeholder="random"
+                    inputmode="numeric"
+                    on:input=update_seed
+                    prop:value=seed_input
+                    class="team-delta-input"
+                />
+                </div>
+                <div class="row">
+                <label for="time-budget-input" class="team-delta-label">Time Budget (seconds):</label>
+                <input
+                    id="time-budget-input"
+                    type="number"
+                    min="0.1"
+                    step="0.5"
+                    value=time_budget_default
+                    on:input=update_time_budget
                     class="team-delta-input"
                 />
                 </div>
                 
-                <button type="submit">Generate Teams</button>
+                <button type="submit" disabled=move || progress.get().is_some()>Generate Teams</button>
+                { move || progress.get().map(|done| view! {
+                    <div class="row">
+                        <progress max="1" value=done></progress>
+                        <button type="button" on:click=cancel_generation>"Cancel"</button>
+                    </div>
+                }) }
             </form>
+            <Pairings players pairings/>
+            <Positions positions/>
+            <SharedPlayer players positions rules=shared_rules/>
+            <Draft players=rated_players num_teams teams team_shared team_gap candidates/>
+            <Adjust players=rated_players constraints teams team_shared team_gap candidates/>
+            { move || (candidates.get().len() > 1).then(|| view! {
+                <div class="row">
+                    <button
+                        disabled=move || candidate.get() == 0
+                        on:click=move |_| show_candidate(candidate.get() - 1)
+                    >"Previous"</button>
+                    <span>"Option "{move || candidate.get() + 1}" of "{move || candidates.get().len()}</span>
+                    <button
+                        disabled=move || candidate.get() + 1 >= candidates.get().len()
+                        on:click=move |_| show_candidate(candidate.get() + 1)
+                    >"Next"</button>
+                </div>
+            }) }
             <div class="row">
             <table id="generated-teams">
                 <tr>
-                    <th class="teamgen-h" contenteditable="true"> Team A </th>
-                    <th class="teamgen-h" contenteditable="true"> Team B </th>
+                    { move || (0..teams.get().len())
+                        .map(|t| view! {
+                            <th class="teamgen-h" contenteditable="true"> "Team "{team_label(t)} </th>
+                        })
+                        .collect_view() }
                 </tr>
-                { move || team_a.get().into_iter()
-                    .zip(team_b.get())
-                    .enumerate()
-                    .map(|(i, (a, b))| view!{ <tr>
-                        <td contenteditable="true" 
-                            style:background-color=move || get_color_code(team_a_color.get())
-                            style:color=move || RGB::from_color(team_a_color.get()).get_text_color()
-                        >{i+1}". "{a.name}</td>
-                        <td contenteditable="true" 
-                            style:background-color=move || get_color_code(team_b_color.get())
-                            style:color=move || RGB::from_color(team_b_color.get()).get_text_color()
-                        >{i+1}". "{b.name}</td>
-                    </tr> })
-                    .collect_view() }
+                { move || {
+                    let teams = listed_teams.get();
+                    let rows = teams.iter().map(Vec::len).max().unwrap_or(0);
+                    (0..rows)
+                        .map(|i| view!{ <tr>
+                            { teams.iter()
+                                .enumerate()
+                                .map(|(t, team)| match team.get(i) {
+                                    Some(p) => {
+                                        let color = team_colors[t];
+                                        view! {
+                                            <td contenteditable="true" 
+                                                style:background-color=move || get_color_code(color.get())
+                                                style:color=move || RGB::from_color(color.get()).get_text_color()
+                                            >{i+1}". "{p.name.clone()}</td>
+                                        }.into_any()
+                                    },
+                                    None => view! { <td></td> }.into_any(),
+                                })
+                                .collect_view() }
+                        </tr> })
+                        .collect_view()
+                } }
                 <tr>
-                    <td>"Score: "{ move || team_a.get().iter().map(|p| p.rating - 5.0).sum::<f32>()}</td>
-                    <td>"Score: "{ move || team_b.get().iter().map(|p| p.rating - 5.0).sum::<f32>()}</td>
+                    { move || listed_teams.get().iter()
+                        .map(|team| view! {
+                            <td>"Score: "{ team.iter().map(|p| p.rating - 5.0).sum::<f32>() }</td>
+                        })
+                        .collect_view() }
                 </tr>
+                <tr>
+                    { move || listed_teams.get().iter()
+                        .map(|team| view! {
+                            <td>"Spread: "{ format!("{:.2}", rating_spread(team)) }</td>
+                        })
+                        .collect_view() }
+                </tr>
+                { move || attributes.get().into_iter()
+                    .map(|attribute| view! { <tr>
+                        { listed_teams.get().iter()
+                            .map(|team| view! {
+                                <td>{attribute.clone()}": "{ attribute_total(team, &attribute) }</td>
+                            })
+                            .collect_view() }
+                    </tr> })
+                    .collect_view() }
+                { move || team_gap.get().map(|gap| view! {
+                    <tr>
+                        <td colspan=move || teams.get().len()>
+                            "Rating Gap: "{gap}
+                            { move || candidates.get().get(candidate.get())
+                                .is_some_and(|c| c.optimal)
+                                .then_some(" (proven best possible)") }
+                        </td>
+                    </tr>
+                }) }
+                { move || team_seed.get().map(|seed| view! {
+                    <tr>
+                        <td colspan=move || teams.get().len()>
+                            "Seed: "{seed}
+                            { move || candidates.get().get(candidate.get())
+                                .is_some_and(|c| !c.complete)
+                                .then_some(" (search ran out of time, so this seed may not replay these teams)") }
+                        </td>
+                    </tr>
+                }) }
             </table>
             </div>
+            { move || match parsed_formation.get() {
+                Some(Ok(lines)) => Some(view! {
+                    <div class="row">
+                        { listed_teams.get().into_iter()
+                            .enumerate()
+                            .map(|(t, team)| {
+                                let color = team_colors[t].get();
+                                view! {
+                                    <Pitch
+                                        team
+                                        formation=lines.clone()
+                                        positions=positions.get()
+                                        color=get_color_code(color.clone())
+                                        text_color=RGB::from_color(color).get_text_color()
+                                    />
+                                }
+                            })
+                            .collect_view() }
+                    </div>
+                }),
+                _ => None,
+            } }
+            // Only worth showing once someone has to sit out or switch teams.
+            { move || {
+                let rotation = rotation.get();
+                let needed = rotation.iter().any(|p| p.shared_team.is_some() || p.sitting_out.iter().any(|s| !s.is_empty()));
+                needed.then(|| view! {
+                    <div class="row">
+                    <table id="rotation">
+                        <tr>
+                            <th>"Period"</th>
+                            { (0..teams.get().len())
+                                .map(|t| view! { <th>"Team "{team_label(t)}" Sitting Out"</th> })
+                                .collect_view() }
+                        </tr>
+                        { rotation.into_iter()
+                            .enumerate()
+                            .map(|(i, period)| view! { <tr>
+                                <td>{i + 1}</td>
+                                { period.sitting_out.into_iter()
+                                    .enumerate()
+                                    .map(|(t, sitting)| {
+                                        let joins = (period.shared_team == Some(t))
+                                            .then(|| team_shared.get().map(|p| format!(" ({} plays for this team)", p.name)))
+                                            .flatten()
+                                            .unwrap_or_default();
+                                        view! { <td>{sitting.join(", ")}{joins}</td> }
+                                    })
+                                    .collect_view() }
+                            </tr> })
+                            .collect_view() }
+                    </table>
+                    </div>
+                })
+            } }
+            <Results players matches teams shared=team_shared/>
         </div>
     }
 }
@@ -0,0 +1,66 @@
+use crate::teamgen::Player;
+
+/// The team that makes the `pick`th pick (counting from 0) of a snake draft, which reverses direction every round.
+pub fn snake_turn(pick: usize, num_teams: usize) -> usize {
+    let (round, slot) = (pick / num_teams, pick % num_teams);
+    if round % 2 == 0 {
+        slot
+    } else {
+        num_teams - 1 - slot
+    }
+}
+
+/// Fills the empty captain slots with the highest rated players not already captaining,
+/// the weakest of them in the first empty slot so they get the earliest pick.
+pub fn auto_captains(players: &[Player], captains: &[String]) -> Vec<String> {
+    let mut players = players.iter()
+        .filter(|p| !captains.contains(&p.name))
+        .collect::<Vec<_>>();
+    players.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+    let empty = captains.iter().filter(|c| c.is_empty()).count();
+    let mut auto = players.into_iter()
+        .take(empty)
+        .rev()
+        .map(|p| p.name.clone());
+    captains.iter()
+        .map(|c| if c.is_empty() { auto.next().unwrap_or_default() } else { c.clone() })
+        .collect()
+}
+
+/// The rating gap between the strongest and weakest team.
+pub fn draft_gap(teams: &[Vec<Player>]) -> f32 {
+    let (min, max) = teams.iter()
+        .map(|t| t.iter().map(|p| p.rating).sum::<f32>())
+        .fold((f32::MAX, f32::MIN), |(min, max), r| (min.min(r), max.max(r)));
+    if teams.is_empty() {
+        0.0
+    } else {
+        max - min
+    }
+}
+
+/// Finishes a draft with every team taking the best player left, returning the final rating gap.
+fn finish_greedily(mut teams: Vec<Vec<Player>>, mut pool: Vec<Player>, mut pick: usize) -> f32 {
+    pool.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+    for p in pool {
+        let team = snake_turn(pick, teams.len());
+        teams[team].push(p);
+        pick += 1;
+    }
+    draft_gap(&teams)
+}
+
+/// Suggests the fairest pick for the team on the clock: the player that leaves the smallest final gap,
+/// assuming every later pick takes the best player left.
+pub fn suggest_pick(teams: &[Vec<Player>], pool: &[Player], pick: usize) -> Option<usize> {
+    let team = snake_turn(pick, teams.len());
+    (0..pool.len())
+        .map(|i| {
+            let mut teams = teams.to_vec();
+            let mut pool = pool.to_vec();
+            teams[team].push(pool.remove(i));
+            (i, finish_greedily(teams, pool, pick + 1))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+}
@@ -0,0 +1,243 @@
+use crate::positions::PositionCatalog;
+use crate::teamgen::Player;
+
+/// One line of a formation, from the keeper forwards.
+#[derive(Clone, PartialEq)]
+pub struct Line {
+    /// The position every slot in this line plays
+    pub position: String,
+    pub size: usize,
+}
+
+/// Parses a formation like `4-4-2`, written from the back, with positions from the `catalog`.
+///
+/// A keeper is added in front of the lines, unless the formation has at least four lines and starts with a 1,
+/// like `1-2-3-1` for 7-a-side, in which case that 1 is the keeper.
+/// Each line can name the position it plays, like `3 cb-4-3`. Otherwise the keeper is the catalog's goalkeeper,
+/// the first outfield line its defenders, the last one its forwards, and any lines between its midfielders.
+pub fn parse_formation(formation: &str, catalog: &PositionCatalog) -> Result<Vec<Line>, String> {
+    let mut written = formation.split('-')
+        .map(|line| {
+            let line = line.trim();
+            let (size, position) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let size = size.parse::<usize>().ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("\"{formation}\" isn't a formation like 4-4-2."))?;
+            let position = match position.trim() {
+                "" => None,
+                named => Some(catalog.find(named)
+                    .ok_or_else(|| format!("\"{named}\" isn't a position in the catalog."))?
+                    .to_string()),
+            };
+            Ok((size, position))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let keeper = if written.len() >= 4 && written[0].0 == 1 {
+        written.remove(0).1
+    } else {
+        None
+    };
+
+    let outfield = written.len();
+    let mut lines = vec![Line { position: keeper.unwrap_or_else(|| catalog.normalize("goalkeeper")), size: 1 }];
+    lines.extend(written.into_iter().enumerate().map(|(i, (size, position))| {
+        let role = match i {
+            _ if outfield == 1 => "midfielder",
+            0 => "defender",
+            i if i + 1 == outfield => "forward",
+            _ => "midfielder",
+        };
+        Line { position: position.unwrap_or_else(|| catalog.normalize(role)), size }
+    }));
+    Ok(lines)
+}
+
+/// A team laid out in a formation.
+pub struct Assignment {
+    /// For each line, the player in each slot, if there were enough players
+    pub lines: Vec<Vec<Option<String>>>,
+    /// Players left over once every slot is filled
+    pub subs: Vec<String>,
+}
+
+/// How poorly a player suits a position: their first listed position fits best, and unlisted ones worst.
+fn fit(player: &Player, position: &str, catalog: &PositionCatalog) -> i64 {
+    let position = catalog.normalize(position);
+    match &player.position {
+        Some(listed) => match listed.iter().position(|p| catalog.normalize(p) == position) {
+            Some(rank) => rank as i64,
+            None => 10 + listed.len() as i64,
+        },
+        // Anyone without a listed position is happy anywhere, just not ahead of someone who asked for it.
+        None => 5,
+    }
+}
+
+/// Puts each player in the formation slot that best suits their listed positions.
+pub fn assign_formation(team: &[Player], formation: &[Line], catalog: &PositionCatalog) -> Assignment {
+    let slots = formation.iter()
+        .flat_map(|line| std::iter::repeat_n(line.position.as_str(), line.size))
+        .collect::<Vec<_>>();
+
+    // Empty slots and the bench cost the same for everyone, so padding to a square keeps the assignment exact.
+    let n = team.len().max(slots.len());
+    let cost = (0..n)
+        .map(|i| (0..n)
+            .map(|j| match (team.get(i), slots.get(j)) {
+                (Some(p), Some(pos)) => fit(p, pos, catalog),
+                _ => 0,
+            })
+            .collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let slot_of = hungarian(&cost);
+
+    let mut filled = vec![None; slots.len()];
+    let mut subs = vec![];
+    for (i, p) in team.iter().enumerate() {
+        match filled.get_mut(slot_of[i]) {
+            Some(slot) => *slot = Some(p.name.clone()),
+            None => subs.push(p.name.clone()),
+        }
+    }
+
+    let mut filled = filled.into_iter();
+    let lines = formation.iter()
+        .map(|line| filled.by_ref().take(line.size).collect())
+        .collect();
+    Assignment { lines, subs }
+}
+
+/// Solves the square assignment problem, returning the column for each row of the cheapest matching.
+fn hungarian(cost: &[Vec<i64>]) -> Vec<usize> {
+    let n = cost.len();
+    // Potentials and the matching are 1-indexed, with 0 as a sentinel column.
+    let mut u = vec![0; n + 1];
+    let mut v = vec![0; n + 1];
+    let mut row_of = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min_to = vec![i64::MAX; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut delta = i64::MAX;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                if reduced < min_to[j] {
+                    min_to[j] = reduced;
+                    way[j] = j0;
+                }
+                if min_to[j] < delta {
+                    delta = min_to[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_to[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut col_of = vec![0; n];
+    for j in 1..=n {
+        if row_of[j] > 0 {
+            col_of[row_of[j] - 1] = j - 1;
+        }
+    }
+    col_of
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    /// The cheapest total of any assignment, trying every one.
+    fn cheapest(cost: &[Vec<i64>], row: usize, used: &mut Vec<bool>) -> i64 {
+        if row == cost.len() {
+            return 0;
+        }
+        let mut best = i64::MAX;
+        for col in 0..cost.len() {
+            if !used[col] {
+                used[col] = true;
+                best = best.min(cost[row][col] + cheapest(cost, row + 1, used));
+                used[col] = false;
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn finds_the_cheapest_assignment() {
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        for _ in 0..200 {
+            let n = rng.gen_range(1..=6);
+            let cost = (0..n)
+                .map(|_| (0..n).map(|_| rng.gen_range(0..20)).collect::<Vec<i64>>())
+                .collect::<Vec<_>>();
+            let col_of = hungarian(&cost);
+
+            let mut cols = col_of.clone();
+            cols.sort();
+            assert_eq!(cols, (0..n).collect::<Vec<_>>(), "{cost:?}");
+            let total = col_of.iter().enumerate().map(|(row, &col)| cost[row][col]).sum::<i64>();
+            assert_eq!(total, cheapest(&cost, 0, &mut vec![false; n]), "{cost:?}");
+        }
+        assert_eq!(hungarian(&[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn puts_players_in_their_listed_positions() {
+        let catalog = PositionCatalog::default();
+        let player = |name: &str, positions: &[&str]| Player {
+            name: name.to_string(),
+            rating: 5.0,
+            gender: false,
+            fixed_team: None,
+            position: Some(positions.iter().map(|p| p.to_string()).collect()),
+            pairings: vec![],
+            attributes: Default::default(),
+        };
+        let team = [
+            player("Fay", &["striker"]),
+            player("Dan", &["defender", "fw"]),
+            player("Gus", &["goalkeeper"]),
+            player("Mo", &["mid"]),
+            player("Sub", &["gk"]),
+        ];
+        let formation = parse_formation("1-1-1", &catalog).unwrap();
+        let assignment = assign_formation(&team, &formation, &catalog);
+        let slot = |name: &str| Some(name.to_string());
+        assert_eq!(assignment.lines, vec![vec![slot("Gus")], vec![slot("Dan")], vec![slot("Mo")], vec![slot("Fay")]]);
+        assert_eq!(assignment.subs, vec!["Sub".to_string()]);
+    }
+}
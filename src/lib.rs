@@ -0,0 +1,5 @@
+//! The parts of the app that also run in the team generation worker.
+
+pub mod positions;
+pub mod teamgen;
+pub mod worker;
@@ -1,9 +1,14 @@
 mod app;
-mod teamgen;
 mod components;
 mod utils;
+mod rotation;
+mod formation;
+mod ratings;
+mod draft;
+mod sheet;
 
 use app::*;
+use teambuilder_ui::{positions, teamgen, worker};
 use leptos::prelude::*;
 
 fn main() {
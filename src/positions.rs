@@ -0,0 +1,67 @@
+use serde::{Serialize, Deserialize};
+
+use crate::teamgen::Player;
+
+/// The keeper's position by its default name, which can't be the shared player unless allowed.
+pub const KEEPER: &str = "gk";
+
+/// A position players can list, with the other names it goes by on sheets.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Position {
+    /// The name every alias is normalized to
+    pub name: String,
+    pub aliases: Vec<String>,
+}
+
+/// Every position the app knows, used to normalize whatever a sheet calls them.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct PositionCatalog {
+    pub positions: Vec<Position>,
+}
+
+impl Default for PositionCatalog {
+    fn default() -> Self {
+        let position = |name: &str, aliases: &[&str]| Position {
+            name: name.to_string(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        };
+        PositionCatalog {
+            positions: vec![
+                position("gk", &["g", "goalkeeper", "goalie", "keeper"]),
+                position("df", &["d", "def", "defender", "defense", "defence", "cb", "lb", "rb", "fb"]),
+                position("mid", &["m", "mf", "midfield", "midfielder", "cm", "dm", "am", "lm", "rm"]),
+                position("fw", &["f", "fwd", "forward", "striker", "st", "cf", "att", "attacker", "lw", "rw"]),
+            ],
+        }
+    }
+}
+
+impl PositionCatalog {
+    /// The name of the catalog position `value` refers to, ignoring case, if the catalog knows it.
+    pub fn find(&self, value: &str) -> Option<&str> {
+        let value = value.trim();
+        self.positions.iter()
+            .find(|p| p.name.eq_ignore_ascii_case(value) || p.aliases.iter().any(|a| a.trim().eq_ignore_ascii_case(value)))
+            .map(|p| p.name.as_str())
+    }
+
+    /// The name of the position `value` refers to, ignoring case.
+    /// Values the catalog doesn't know are just trimmed and lowercased.
+    pub fn normalize(&self, value: &str) -> String {
+        self.find(value).map_or_else(|| value.trim().to_lowercase(), str::to_string)
+    }
+
+    /// A copy of the player with their listed positions normalized, keeping the first mention of each.
+    pub fn normalize_player(&self, player: &Player) -> Player {
+        let position = player.position.as_ref().map(|listed| {
+            let mut normalized: Vec<String> = vec![];
+            for pos in listed.iter().map(|p| self.normalize(p)) {
+                if !normalized.contains(&pos) {
+                    normalized.push(pos);
+                }
+            }
+            normalized
+        });
+        Player { position, ..player.clone() }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::teamgen::Player;
+
+/// How many rating points a player moves after a completely unexpected result.
+const K: f32 = 0.5;
+
+/// The average rating difference at which the stronger team is expected to win 10 games to 1.
+const SCALE: f32 = 4.0;
+
+/// The score of one game, played by teams the generator made.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MatchResult {
+    /// When the game was recorded, as an ISO 8601 timestamp
+    pub played: String,
+    /// The names of each team's players
+    pub teams: Vec<Vec<String>>,
+    /// The player who split their time between the teams
+    #[serde(default)]
+    pub shared: Option<String>,
+    /// Goals scored by each team
+    pub scores: Vec<u32>,
+}
+
+impl MatchResult {
+    /// The index of the team each player was on, leaving out the shared player.
+    pub fn team_of(&self) -> BTreeMap<String, usize> {
+        self.teams.iter()
+            .enumerate()
+            .flat_map(|(t, team)| team.iter().map(move |name| (name.clone(), t)))
+            .collect()
+    }
+}
+
+/// Which ratings to balance teams with.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RatingSource {
+    /// The ratings typed into the sheet
+    Manual,
+    /// Ratings learned from recorded results
+    Learned,
+    /// The given share of the learned rating, with the rest from the manual rating
+    Blend(f32),
+}
+
+/// A player's rating after each recorded game they played, oldest first.
+pub type RatingHistory = BTreeMap<String, Vec<f32>>;
+
+/// Replays every recorded game with Elo, starting from the sheet ratings.
+///
+/// Teams are compared by their average rating, and every player on a team moves by the same amount.
+/// With more than two teams, each team is scored against every other one.
+/// The shared player counts for half on each team they played for.
+/// Results without exactly one score per team are skipped.
+pub fn learn_ratings(players: &[Player], matches: &[MatchResult]) -> RatingHistory {
+    let mut ratings: BTreeMap<String, f32> = players.iter()
+        .map(|p| (p.name.clone(), p.rating))
+        .collect();
+    let mut history = RatingHistory::new();
+
+    for result in matches {
+        // A result saved without a score for every team can't say who won.
+        if result.scores.len() != result.teams.len() {
+            continue;
+        }
+        // Players who have left the roster, or never had a manual rating, start from the average.
+        let default = ratings.values().sum::<f32>() / ratings.len().max(1) as f32;
+        let rating = |ratings: &BTreeMap<String, f32>, name: &String| ratings.get(name).copied().unwrap_or(default);
+        let strength = result.teams.iter()
+            .map(|team| team.iter().map(|name| rating(&ratings, name)).sum::<f32>() / team.len().max(1) as f32)
+            .collect::<Vec<_>>();
+
+        let mut changes = vec![0.0; result.teams.len()];
+        for t in 0..result.teams.len() {
+            for u in 0..result.teams.len() {
+                if t == u {
+                    continue;
+                }
+                let expected = 1.0 / (1.0 + 10f32.powf((strength[u] - strength[t]) / SCALE));
+                let actual = match result.scores[t].cmp(&result.scores[u]) {
+                    std::cmp::Ordering::Greater => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Less => 0.0,
+                };
+                // Bigger wins say more about the teams than narrow ones.
+                let margin = (result.scores[t].abs_diff(result.scores[u]) as f32 + 1.0).ln() + 1.0;
+                changes[t] += K * margin * (actual - expected) / (result.teams.len() - 1) as f32;
+            }
+        }
+
+        let mut updated: BTreeMap<&String, f32> = BTreeMap::new();
+        for (team, change) in result.teams.iter().zip(&changes) {
+            for name in team {
+                *updated.entry(name).or_insert(0.0) += change;
+            }
+        }
+        if let Some(shared) = &result.shared {
+            let change = changes.iter().sum::<f32>() / changes.len().max(1) as f32;
+            *updated.entry(shared).or_insert(0.0) += change;
+        }
+
+        for (name, change) in updated {
+            let new = rating(&ratings, name) + change;
+            ratings.insert(name.clone(), new);
+            history.entry(name.clone()).or_default().push(new);
+        }
+    }
+    history
+}
+
+impl RatingSource {
+    /// The rating to balance a player with, given what was learned about them.
+    pub fn rating(&self, player: &Player, history: &RatingHistory) -> f32 {
+        let learned = history.get(&player.name)
+            .and_then(|h| h.last().copied())
+            .unwrap_or(player.rating);
+        match *self {
+            RatingSource::Manual => player.rating,
+            RatingSource::Learned => learned,
+            RatingSource::Blend(share) => share * learned + (1.0 - share) * player.rating,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::teamgen::Player;
+
+/// Who plays where for one period of the game.
+#[derive(Clone)]
+pub struct Period {
+    /// For each team, the players sitting out this period
+    pub sitting_out: Vec<Vec<String>>,
+    /// The team the shared player plays for this period
+    pub shared_team: Option<usize>,
+}
+
+/// The team a shared player joins for `period`, giving each team an equal, unbroken stretch of the game.
+pub fn shared_team(period: usize, periods: usize, num_teams: usize) -> usize {
+    period * num_teams / periods
+}
+
+/// Plans substitutions so each team fields `on_field` players per period and everyone gets equal playing time.
+///
+/// Players take turns on the bench in order, so playing time differs by at most one period within a team.
+/// The shared player moves between the teams as described by [`shared_team`] and never sits out.
+/// Leaving `on_field` empty lets every player on a team play every period,
+/// unless the shared player is a `substitute`, who always comes on for someone.
+pub fn plan_rotation(
+    teams: &[Vec<Player>],
+    shared: Option<&Player>,
+    substitute: bool,
+    on_field: Option<usize>,
+    periods: usize,
+) -> Vec<Period> {
+    // Each team's bench queue, the front of which sits out next.
+    let mut queues = teams.iter()
+        .map(|team| team.iter().map(|p| p.name.clone()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    (0..periods)
+        .map(|period| {
+            let shared_team = shared.map(|_| shared_team(period, periods, teams.len()));
+            let sitting_out = queues.iter_mut()
+                .enumerate()
+                .map(|(t, queue)| {
+                    let present = queue.len() + usize::from(shared_team == Some(t));
+                    let on_field = on_field.or(substitute.then_some(queue.len()));
+                    let bench = on_field.map_or(0, |on_field| present.saturating_sub(on_field)).min(queue.len());
+                    let sitting = queue.drain(..bench).collect::<Vec<_>>();
+                    queue.extend(sitting.iter().cloned());
+                    sitting
+                })
+                .collect();
+            Period { sitting_out, shared_team }
+        })
+        .collect()
+}
@@ -0,0 +1,568 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Cursor;
+use std::sync::LazyLock;
+
+use calamine::{open_workbook_auto_from_rs, Reader};
+use polars::prelude::{CsvReadOptions, DataFrame, SerReader};
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::teamgen::{Pairing, Player};
+
+/// The spreadsheet in a Google Sheets link, with whether it's a "publish to web" link.
+/// Edit, view and `htmlview` links all have the id after `/d/`, sometimes behind the account like `/u/0/`.
+static SHEET_URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:https?://)?docs\.google\.com/spreadsheets/(?:u/\d+/)?d/(e/)?([\w-]+)").unwrap()
+});
+
+/// The tab of a Google Sheets link, from either the query or the fragment.
+static SHEET_GID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[?&#]gid=(\d+)").unwrap());
+
+/// Escapes a tab name for a query string.
+fn encode_query(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Where to download a Google Sheet as CSV, from any link to it.
+///
+/// The tab is the one named `tab`, or else the one in the link, or else the first.
+/// Published links can only pick a tab by the one in the link.
+pub fn csv_export_url(url: &str, tab: &str) -> Result<String, String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("Enter the link to the player sheet".to_string());
+    }
+    let captures = SHEET_URL.captures(url)
+        .ok_or("That isn't a Google Sheets link, which starts with docs.google.com/spreadsheets/d/".to_string())?;
+    let published = captures.get(1).is_some();
+    let id = &captures[2];
+    let gid = SHEET_GID.captures(url).map(|c| c[1].to_string());
+    let tab = tab.trim();
+
+    match (published, tab.is_empty()) {
+        (true, true) => Ok(match gid {
+            Some(gid) => format!("https://docs.google.com/spreadsheets/d/e/{id}/pub?gid={gid}&single=true&output=csv"),
+            None => format!("https://docs.google.com/spreadsheets/d/e/{id}/pub?output=csv"),
+        }),
+        (true, false) => Err(
+            "Published links can't pick a tab by name, so publish the tab you want and use its link".to_string()
+        ),
+        (false, true) => Ok(format!(
+            "https://docs.google.com/spreadsheets/d/{id}/export?format=csv&gid={}",
+            gid.as_deref().unwrap_or("0"),
+        )),
+        (false, false) => Ok(format!(
+            "https://docs.google.com/spreadsheets/d/{id}/gviz/tq?tqx=out:csv&sheet={}",
+            encode_query(tab),
+        )),
+    }
+}
+
+/// Writes rows of cells as CSV, quoting the cells that need it.
+fn write_csv(rows: impl IntoIterator<Item = Vec<String>>) -> String {
+    rows.into_iter()
+        .map(|row| row.iter()
+            .map(|cell| if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            })
+            .collect::<Vec<_>>()
+            .join(","))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reads a roster file as CSV, so it's read like a downloaded sheet.
+///
+/// Spreadsheets are read from their first tab. JSON rosters are a list of objects, with the headers as keys.
+pub fn file_to_csv(name: &str, bytes: &[u8]) -> Result<String, String> {
+    let extension = name.rsplit_once('.').map(|(_, e)| e.to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "csv" | "txt" => String::from_utf8(bytes.to_vec()).map_err(|_| format!("{name} isn't a text file")),
+        "json" => {
+            let rows: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_slice(bytes)
+                .map_err(|e| format!("{name} isn't a list of players: {e}"))?;
+            // Every key any player has.
+            let mut headers: Vec<&String> = vec![];
+            for key in rows.iter().flat_map(|row| row.keys()) {
+                if !headers.contains(&key) {
+                    headers.push(key);
+                }
+            }
+            let cells = rows.iter().map(|row| headers.iter()
+                .map(|&header| match row.get(header) {
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    Some(serde_json::Value::Null) | None => String::new(),
+                    Some(value) => value.to_string(),
+                })
+                .collect());
+            Ok(write_csv(std::iter::once(headers.iter().map(|h| h.to_string()).collect()).chain(cells)))
+        },
+        "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => {
+            let mut workbook = open_workbook_auto_from_rs(Cursor::new(bytes.to_vec()))
+                .map_err(|e| format!("{name} couldn't be opened: {e}"))?;
+            let range = workbook.worksheet_range_at(0)
+                .ok_or(format!("{name} has no tabs"))?
+                .map_err(|e| format!("{name} couldn't be read: {e}"))?;
+            Ok(write_csv(range.rows().map(|row| row.iter().map(|cell| cell.to_string()).collect())))
+        },
+        _ => Err(format!("{name} isn't a CSV, Excel, ODS or JSON file")),
+    }
+}
+
+/// A column the player sheet is read from.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Field {
+    FirstName,
+    LastName,
+    Gender,
+    Rating,
+    Attending,
+    TeamLock,
+    Position,
+    Pairings,
+}
+
+impl Field {
+    pub const ALL: [Field; 8] = [
+        Field::FirstName,
+        Field::LastName,
+        Field::Gender,
+        Field::Rating,
+        Field::Attending,
+        Field::TeamLock,
+        Field::Position,
+        Field::Pairings,
+    ];
+
+    /// What the field is called in the app.
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::FirstName => "First Name",
+            Field::LastName => "Last Name",
+            Field::Gender => "Gender",
+            Field::Rating => "Rating",
+            Field::Attending => "Attending",
+            Field::TeamLock => "Team Lock",
+            Field::Position => "Position",
+            Field::Pairings => "Pairings",
+        }
+    }
+
+    /// The header the column has in the club's sheet, which is what it's read from unless mapped otherwise.
+    pub fn header(self) -> &'static str {
+        match self {
+            Field::FirstName => "fName",
+            Field::LastName => "lName",
+            Field::Gender => "Gender",
+            Field::Rating => "Rating",
+            Field::Attending => "Attending",
+            Field::TeamLock => "Team",
+            Field::Position => "Position",
+            Field::Pairings => "Pairings",
+        }
+    }
+
+    /// Whether a sheet without this column can't be read at all.
+    fn required(self) -> bool {
+        matches!(self, Field::FirstName | Field::Rating | Field::Attending)
+    }
+}
+
+/// What a sheet column holds.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Column {
+    Field(Field),
+    /// An extra rating, named by the header
+    Attribute,
+    /// Nothing the app uses
+    Ignored,
+}
+
+/// How a sheet's columns are read, by header. Headers that aren't listed are read by their default meaning.
+pub type ColumnMap = BTreeMap<String, Column>;
+
+/// What the column with `header` holds: the mapped field, or else the field with that default header.
+///
+/// Other columns, like phone numbers or ages, are ignored until they're mapped as extra ratings.
+pub fn column_for(header: &str, mapping: &ColumnMap) -> Column {
+    let header = header.trim();
+    if let Some(&column) = mapping.get(header) {
+        return column;
+    }
+    Field::ALL.into_iter()
+        .find(|f| f.header().eq_ignore_ascii_case(header))
+        .map_or(Column::Ignored, Column::Field)
+}
+
+/// Why a row of the player sheet couldn't be read, with the row number as the sheet shows it.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RowError {
+    /// A required column is missing from the header, or blank in the row
+    MissingColumn { row: usize, column: String },
+    /// The rating isn't a number
+    BadRating { row: usize, value: String },
+    /// The team lock isn't a team letter
+    UnknownTeamLock { row: usize, value: String },
+    /// The attending answer isn't a yes or a no
+    BadAttending { row: usize, value: String },
+    /// The sheet isn't valid CSV from this row on
+    Unreadable { row: usize, message: String },
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowError::MissingColumn { row, column } => write!(f, "Row {row}: missing the \"{column}\" column"),
+            RowError::BadRating { row, value } => write!(f, "Row {row}: the rating \"{value}\" isn't a number"),
+            RowError::UnknownTeamLock { row, value } => {
+                write!(f, "Row {row}: the team lock \"{value}\" isn't a team letter like A or B")
+            },
+            RowError::BadAttending { row, value } => {
+                write!(f, "Row {row}: the attending answer \"{value}\" isn't a yes or a no, like y or n")
+            },
+            RowError::Unreadable { row, message } => write!(f, "Row {row}: {message}"),
+        }
+    }
+}
+
+/// The row each record of a CSV player sheet starts on, numbered from 1 like the sheet does and header first,
+/// and the rows under the header.
+///
+/// The header is the first line with a column read as the first name; anything above it is ignored.
+/// When no line has one, the header is the first of the lines with the most filled in cells, which skips any titles.
+fn read_sheet(csv: &str, mapping: &ColumnMap) -> Result<(Vec<usize>, DataFrame), RowError> {
+    let lines = csv.split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((line, start))
+        })
+        .collect::<Vec<_>>();
+    let is_header = |line: &str| line.split(',')
+        .any(|cell| column_for(cell.trim().trim_matches('"'), mapping) == Column::Field(Field::FirstName));
+    let (header_line, offset) = lines.iter()
+        .position(|(line, _)| is_header(line))
+        .or_else(|| {
+            let filled = |line: &str| line.split(',').filter(|cell| !cell.trim().is_empty()).count();
+            let most = lines.iter().map(|(line, _)| filled(line)).max().filter(|&most| most > 0)?;
+            lines.iter().position(|(line, _)| filled(line) == most)
+        })
+        .map(|i| (i, lines[i].1))
+        .ok_or(RowError::MissingColumn { row: 1, column: Field::FirstName.label().to_string() })?;
+    let header_row = header_line + 1;
+
+    let frame = CsvReadOptions::default()
+        .with_has_header(true)
+        // Every column is read as text, so each field can be checked and reported on its own.
+        .with_infer_schema_length(Some(0))
+        .map_parse_options(|options| options.with_truncate_ragged_lines(true))
+        .into_reader_with_file_handle(Cursor::new(csv.as_bytes()[offset..].to_vec()))
+        .finish()
+        .map_err(|e| RowError::Unreadable { row: header_row, message: e.to_string() })?;
+    let rows = record_lines(&csv[offset..]).into_iter().map(|line| header_row + line).collect();
+    Ok((rows, frame))
+}
+
+/// The line each record of `csv` starts on, counting from 0, as a quoted cell can span several lines.
+fn record_lines(csv: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut line = 0;
+    let mut quoted = false;
+    let mut field_start = true;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // A doubled quote is a quote inside the cell.
+            '"' if quoted && chars.next_if_eq(&'"').is_none() => quoted = false,
+            '"' if field_start => quoted = true,
+            '\n' => {
+                line += 1;
+                if !quoted {
+                    starts.push(line);
+                }
+            },
+            _ => (),
+        }
+        field_start = !quoted && matches!(c, ',' | '\n');
+    }
+    starts
+}
+
+/// The headers of a CSV player sheet, in order.
+pub fn sheet_headers(csv: &str, mapping: &ColumnMap) -> Vec<String> {
+    read_sheet(csv, mapping)
+        .map(|(_, frame)| frame.get_column_names().into_iter().map(|name| name.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// The attending players of a CSV player sheet, along with every row that couldn't be read.
+///
+/// Columns are found by their header through `mapping`, so they can be in any order.
+pub fn parse_players(csv: &str, mapping: &ColumnMap) -> (Vec<Player>, Vec<RowError>) {
+    let (rows, frame) = match read_sheet(csv, mapping) {
+        Ok(sheet) => sheet,
+        Err(error) => return (vec![], vec![error]),
+    };
+    let header_row = rows[0];
+
+    let mut columns: BTreeMap<Field, Vec<Option<String>>> = BTreeMap::new();
+    let mut attributes: Vec<(String, Vec<Option<String>>)> = vec![];
+    for column in frame.get_columns() {
+        let Ok(values) = column.str() else {
+            continue;
+        };
+        let values = values.into_iter().map(|v| v.map(str::to_string)).collect();
+        match column_for(column.name(), mapping) {
+            Column::Field(field) => {
+                columns.entry(field).or_insert(values);
+            },
+            Column::Attribute => attributes.push((column.name().trim().to_lowercase(), values)),
+            Column::Ignored => (),
+        }
+    }
+
+    let missing = Field::ALL.into_iter()
+        .filter(|f| f.required() && !columns.contains_key(f))
+        .map(|f| RowError::MissingColumn { row: header_row, column: f.label().to_string() })
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return (vec![], missing);
+    }
+
+    let mut players = vec![];
+    let mut errors = vec![];
+    for i in 0..frame.height() {
+        let cell = |field: Field| columns.get(&field)
+            .and_then(|values| values[i].as_deref())
+            .map(str::trim)
+            .unwrap_or_default();
+        // Blank lines at the bottom of the sheet aren't players.
+        if Field::ALL.into_iter().all(|f| cell(f).is_empty()) {
+            continue;
+        }
+        let row = rows.get(i + 1).copied().unwrap_or(header_row + 1 + i);
+        let attributes = attributes.iter()
+            .map(|(attribute, values)| (attribute.as_str(), values[i].as_deref().unwrap_or_default()));
+        match parse_row(row, cell, attributes) {
+            Ok(Some(player)) => players.push(player),
+            Ok(None) => (),
+            Err(error) => errors.push(error),
+        }
+    }
+    (players, errors)
+}
+
+/// Reads one row, or `None` for a player who isn't attending.
+fn parse_row<'a>(
+    row: usize,
+    cell: impl Fn(Field) -> &'a str,
+    attributes: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Result<Option<Player>, RowError> {
+    let required = |field: Field| match cell(field) {
+        "" => Err(RowError::MissingColumn { row, column: field.label().to_string() }),
+        s => Ok(s),
+    };
+
+    // Sheets answer with a letter, while spreadsheet and JSON files answer with a boolean.
+    let attending = cell(Field::Attending);
+    match attending.to_lowercase().as_str() {
+        "y" | "yes" | "true" | "1" => (),
+        "" | "n" | "no" | "false" | "0" => return Ok(None),
+        _ => return Err(RowError::BadAttending { row, value: attending.to_string() }),
+    }
+
+    let mut name = required(Field::FirstName)?.to_string();
+    let last_name = cell(Field::LastName);
+    if !last_name.is_empty() {
+        name.push(' ');
+        name.push_str(last_name);
+    }
+    let gender = cell(Field::Gender).eq_ignore_ascii_case("F");
+    let rating = required(Field::Rating)?;
+    let rating = rating.parse()
+        .map_err(|_| RowError::BadRating { row, value: rating.to_string() })?;
+
+    // Teams are locked by letter, `A` for the first team, `B` for the second and so on.
+    let fixed_team = match cell(Field::TeamLock) {
+        "" => None,
+        s => match s.to_ascii_uppercase().as_bytes() {
+            &[c @ b'A'..=b'Z'] => Some((c - b'A') as usize),
+            _ => return Err(RowError::UnknownTeamLock { row, value: s.to_string() }),
+        },
+    };
+
+    let position = match cell(Field::Position) {
+        "" => None,
+        s => Some(s.split('/').map(|s| s.trim().to_lowercase()).collect()),
+    };
+
+    // Pairings are written as `+Name` to keep a player together with this one, or `-Name` to keep them apart,
+    // separated by `/` like positions.
+    let pairings = cell(Field::Pairings)
+        .split('/')
+        .filter_map(|s| {
+            let s = s.trim();
+            let (together, other) = if let Some(other) = s.strip_prefix('+') {
+                (true, other)
+            } else {
+                (false, s.strip_prefix('-')?)
+            };
+            Some(Pairing {
+                first: name.clone(),
+                second: other.trim().to_string(),
+                together,
+            })
+        })
+        .collect();
+
+    // Blank or unreadable attribute ratings are left out.
+    let attributes = attributes
+        .filter_map(|(attribute, s)| Some((attribute.to_string(), s.trim().parse().ok()?)))
+        .collect();
+
+    Ok(Some(Player {
+        name,
+        rating,
+        gender,
+        fixed_team,
+        position,
+        pairings,
+        attributes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "1AbC-d_EfG";
+
+    fn export(gid: &str) -> Result<String, String> {
+        Ok(format!("https://docs.google.com/spreadsheets/d/{ID}/export?format=csv&gid={gid}"))
+    }
+
+    #[test]
+    fn reads_the_tab_from_edit_and_view_links() {
+        let links = [
+            (format!("https://docs.google.com/spreadsheets/d/{ID}/edit#gid=123"), "123"),
+            (format!("https://docs.google.com/spreadsheets/d/{ID}/edit?usp=sharing"), "0"),
+            (format!("https://docs.google.com/spreadsheets/d/{ID}/edit?gid=45#gid=45"), "45"),
+            (format!("https://docs.google.com/spreadsheets/u/0/d/{ID}/htmlview?gid=9"), "9"),
+            (format!("http://docs.google.com/spreadsheets/d/{ID}"), "0"),
+            (format!("docs.google.com/spreadsheets/d/{ID}/view"), "0"),
+            (format!("  https://docs.google.com/spreadsheets/d/{ID}/edit#gid=2  "), "2"),
+        ];
+        for (link, gid) in links {
+            assert_eq!(csv_export_url(&link, ""), export(gid), "{link}");
+        }
+    }
+
+    #[test]
+    fn picks_a_tab_by_name() {
+        let link = format!("https://docs.google.com/spreadsheets/d/{ID}/edit#gid=123");
+        assert_eq!(
+            csv_export_url(&link, " Week 1/2 "),
+            Ok(format!("https://docs.google.com/spreadsheets/d/{ID}/gviz/tq?tqx=out:csv&sheet=Week%201%2F2")),
+        );
+    }
+
+    #[test]
+    fn reads_published_links() {
+        let published = "https://docs.google.com/spreadsheets/d/e/2PACX-1vQ_x";
+        assert_eq!(
+            csv_export_url(&format!("{published}/pubhtml?gid=7&single=true"), ""),
+            Ok("https://docs.google.com/spreadsheets/d/e/2PACX-1vQ_x/pub?gid=7&single=true&output=csv".to_string()),
+        );
+        assert_eq!(
+            csv_export_url(&format!("{published}/pubhtml"), ""),
+            Ok("https://docs.google.com/spreadsheets/d/e/2PACX-1vQ_x/pub?output=csv".to_string()),
+        );
+        assert!(csv_export_url(&format!("{published}/pubhtml"), "Week 1").is_err());
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        for link in [
+            "",
+            "   ",
+            "https://example.com/spreadsheets/d/abc",
+            "https://docs.google.com/document/d/abc/edit",
+            "https://drive.google.com/file/d/abc/view",
+        ] {
+            assert!(csv_export_url(link, "").is_err(), "{link}");
+        }
+    }
+
+    fn names(players: &[Player]) -> Vec<&str> {
+        players.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_columns_by_header_below_any_title() {
+        let csv = "Thursday Football,,\n\nRating,Attending,lName,fName,Team,Position\n7.5,y,\"Smith, Jr\",Amy,b,Mid / GK\n";
+        let (players, errors) = parse_players(csv, &ColumnMap::new());
+        assert_eq!(errors, vec![]);
+        assert_eq!(names(&players), vec!["Amy Smith, Jr"]);
+        assert_eq!(players[0].rating, 7.5);
+        assert_eq!(players[0].fixed_team, Some(1));
+        assert_eq!(players[0].position, Some(vec!["mid".to_string(), "gk".to_string()]));
+    }
+
+    #[test]
+    fn skips_blank_lines() {
+        let csv = "fName,Rating,Attending\nAmy,5,y\n\n,,\nBob,6,y\n\n\n";
+        let (players, errors) = parse_players(csv, &ColumnMap::new());
+        assert_eq!(errors, vec![]);
+        assert_eq!(names(&players), vec!["Amy", "Bob"]);
+    }
+
+    #[test]
+    fn reports_bad_ratings_by_the_row_they_start_on() {
+        let csv = "fName,lName,Rating,Attending\nAmy,\"Two\nLines\",5,y\nBob,,six,y\nCat,,,y\n";
+        let (players, errors) = parse_players(csv, &ColumnMap::new());
+        assert_eq!(names(&players), vec!["Amy Two\nLines"]);
+        assert_eq!(errors, vec![
+            RowError::BadRating { row: 4, value: "six".to_string() },
+            RowError::MissingColumn { row: 5, column: "Rating".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn reads_team_locks_by_letter() {
+        let csv = "fName,Rating,Attending,Team\nAmy,5,y,a\nBob,5,y,C\nCat,5,y,\nDan,5,y,AB\nEve,5,y,1\n";
+        let (players, errors) = parse_players(csv, &ColumnMap::new());
+        assert_eq!(players.iter().map(|p| p.fixed_team).collect::<Vec<_>>(), vec![Some(0), Some(2), None]);
+        assert_eq!(errors, vec![
+            RowError::UnknownTeamLock { row: 5, value: "AB".to_string() },
+            RowError::UnknownTeamLock { row: 6, value: "1".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn reads_attending_answers() {
+        let answers = ["y", "Yes", "TRUE", "1", "", "n", "No", "false", "0", "maybe"];
+        let csv = std::iter::once("fName,Rating,Attending".to_string())
+            .chain(answers.iter().enumerate().map(|(i, answer)| format!("P{i},5,{answer}")))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (players, errors) = parse_players(&csv, &ColumnMap::new());
+        assert_eq!(names(&players), vec!["P0", "P1", "P2", "P3"]);
+        assert_eq!(errors, vec![RowError::BadAttending { row: 11, value: "maybe".to_string() }]);
+    }
+
+    #[test]
+    fn reports_missing_columns_at_the_header() {
+        let csv = "Title\nfName,Attending\nAmy,y\n";
+        let (players, errors) = parse_players(csv, &ColumnMap::new());
+        assert!(players.is_empty());
+        assert_eq!(errors, vec![RowError::MissingColumn { row: 2, column: "Rating".to_string() }]);
+    }
+}
@@ -1,176 +1,1128 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use serde::{Serialize, Deserialize};
-use rand::thread_rng;
+use rand::{thread_rng, Rng, SeedableRng};
 use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use web_time::{Duration, Instant};
 
+use crate::positions::{PositionCatalog, KEEPER};
+
+mod adjust;
+mod exact;
+
+pub use adjust::{adjust_teams, Adjustment, Move, Side};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Player {
     pub name: String,
     pub rating: f32,
     pub gender: bool,
-    pub fixed_team: Option<bool>,
+    /// The index of the team this player is locked to
+    pub fixed_team: Option<usize>,
     pub position: Option<Vec<String>>,
+    /// Pairing rules listed for this player on the sheet
+    #[serde(default)]
+    pub pairings: Vec<Pairing>,
+    /// Extra ratings for specific skills, like attack or goalkeeping
+    #[serde(default)]
+    pub attributes: BTreeMap<String, f32>,
+}
+
+/// A rule about whether two players, by name, share a team.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Pairing {
+    pub first: String,
+    pub second: String,
+    /// Whether the pair must play together, rather than apart
+    pub together: bool,
 }
 
+#[derive(Clone)]
 pub struct Team<'a> {
     pub players: Vec<&'a Player>,
     pub half_player: Option<&'a Player>,
 }
 
 impl<'a> Team<'a> {
-    pub fn owned(&'a self, half: usize) -> Vec<Player> {
-        let mut players: Vec<Player> = self.players.iter()
+    /// Clones the team's own players, leaving out the shared player.
+    pub fn owned(&'a self) -> Vec<Player> {
+        self.players.iter()
             .map(|&p| p.clone())
-            .collect();
-
-        match self.half_player {
-            Some(p) => {
-                let name = if half == 1 {
-                    format!("{} (1st half)", p.name)
-                } else if half == 2 {
-                    format!("{} (2nd half)", p.name)
-                } else {
-                    panic!("???? got half {half}");
-                };
-                let p = Player {
-                    name,
-                    fixed_team: p.fixed_team,
-                    gender: p.gender,
-                    rating: p.rating,
-                    position: p.position.clone(),
-                };
-                players.push(p);
-            },
-            None => {},
-        };
-        players
+            .collect()
+    }
+}
+
+/// Describes the part of the game a shared player spends with `team`, such as "1st half".
+pub fn shared_label(team: usize, num_teams: usize) -> String {
+    match (num_teams, team) {
+        (2, 0) => "1st half".to_string(),
+        (2, 1) => "2nd half".to_string(),
+        _ => format!("part {} of {num_teams}", team + 1),
     }
 }
 
 impl Player {
-    pub fn from_row(value: &'_ str) -> Option<Self> {
-        let mut player_fields = value.split(",");
-
-        let mut name = player_fields.next().unwrap().to_string();
-        name.push(' ');
-        name.push_str(player_fields.next().unwrap());
-        let gender = player_fields.next().unwrap() == "F";
-        let rating = player_fields.next().unwrap().parse().unwrap();
-
-        let attending = player_fields.next().unwrap() == "y";
-
-        let fixed_team = if let Some(s) = player_fields.next() {
-            if s == "A" {
-                Some(true)
-            } else if s == "B" {
-                Some(false)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+    /// The player's rating for an attribute, falling back to their overall rating when it isn't listed.
+    pub fn attribute(&self, attribute: &str) -> f32 {
+        self.attributes.get(attribute).copied().unwrap_or(self.rating)
+    }
+}
 
-        let position = if let Some(s) = player_fields.next() {
-            let s = s.trim();
-            if s.is_empty() {
-                None
-            } else {
-                Some(
-                    s.split('/')
-                        .into_iter()
-                        .map(|s| s.trim().to_lowercase())
-                        .collect()
-                )
-            }
-        } else {
-            None
-        };
+/// How many times the local search restarts from a fresh random split.
+const RESTARTS: usize = 250;
 
-        if attending {
-            Some(Player {
-                name,
-                rating,
-                gender,
-                fixed_team,
-                position,
-            })
-        } else {
-            None
+/// One candidate split from a team generation run.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GeneratedTeams {
+    pub teams: Vec<Vec<Player>>,
+    /// The leftover player who splits their time between the teams
+    pub shared: Option<Player>,
+    /// The measured rating gap between the strongest and weakest team
+    pub delta: f32,
+    /// Whether the split meets every requested constraint
+    pub constraints_met: bool,
+    /// The seed that reproduces this run's splits from the same roster
+    pub seed: u64,
+    /// Whether every possible split was checked, so no split scores better than this one
+    pub optimal: bool,
+    /// Whether the search finished within the time budget, so its seed replays this split
+    pub complete: bool,
+    /// Every reason the constraints can't all be met, from [`diagnose`]
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+/// Everything a split is asked to satisfy.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Constraints {
+    /// The largest acceptable rating gap between the strongest and weakest team
+    pub max_delta: f32,
+    /// Minimum number of players per team for each position
+    pub min_positions: BTreeMap<String, usize>,
+    /// Maximum number of players per team for positions that have a limit
+    pub max_positions: BTreeMap<String, usize>,
+    /// Whether the teams' counts for each position may differ by at most one
+    pub even_positions: bool,
+    /// The largest acceptable difference in the number of women (or men) between any two teams
+    pub max_gender_delta: Option<usize>,
+    /// For mixed leagues, the minimum number of women and of men on every team
+    pub min_per_gender: usize,
+    /// Players to keep together or apart, in addition to those listed on the sheet
+    pub pairings: Vec<Pairing>,
+    /// The largest acceptable gap between teams for each attribute that should be balanced
+    pub attribute_tolerances: BTreeMap<String, f32>,
+    /// Each past session's team for every player who played, most recent first
+    pub past_teams: Vec<BTreeMap<String, usize>>,
+    /// How much each pair of teammates from the last session adds to the rating gap, halving for each older session
+    pub repeat_penalty: f32,
+    /// The fewest players who have to change teams since the last session
+    pub min_changes: usize,
+    /// How much the gap in rating spread between teams adds to the rating gap, so teams have similar depth
+    pub depth_weight: f32,
+    /// How many of the highest rated players have to be split as evenly as possible between the teams
+    pub top_split: usize,
+    /// The positions players can list, which every position is compared through
+    pub positions: PositionCatalog,
+    /// Who may be the shared player
+    pub shared_rules: SharedRules,
+    /// How many past sessions each player was the shared player in
+    pub past_shared: BTreeMap<String, usize>,
+    /// How much each past session as the shared player adds to the rating gap, so the role rotates
+    pub shared_repeat_penalty: f32,
+    /// What to do with the player left over from an odd roster
+    pub odd_roster: OddRoster,
+}
+
+/// What to do with a single player left over once the roster is split evenly.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum OddRoster {
+    /// With two teams, they play half the game for each team
+    #[default]
+    Shared,
+    /// One team plays a player up, so the smaller team needs this much more rating per player it's short
+    Uneven(f32),
+    /// They come on for each team in turn as a substitute
+    Substitute,
+}
+
+/// Who may split their time between the teams.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SharedRules {
+    /// Positions whose players can't be shared
+    pub excluded_positions: Vec<String>,
+    /// Players who can't be shared, by name
+    pub excluded_players: Vec<String>,
+    pub min_rating: Option<f32>,
+    pub max_rating: Option<f32>,
+}
+
+impl Default for SharedRules {
+    fn default() -> Self {
+        SharedRules {
+            excluded_positions: vec![KEEPER.to_string()],
+            excluded_players: vec![],
+            min_rating: None,
+            max_rating: None,
         }
     }
 }
 
+impl SharedRules {
+    /// Whether the player may be the shared player, with their positions and the excluded ones already normalized.
+    pub fn allows(&self, player: &Player) -> bool {
+        !player.position.iter().flatten().any(|pos| self.excluded_positions.contains(pos))
+            && !self.excluded_players.contains(&player.name)
+            && self.min_rating.is_none_or(|min| player.rating >= min)
+            && self.max_rating.is_none_or(|max| player.rating <= max)
+    }
+}
+
+impl Constraints {
+    /// Normalizes the positions named by the minimums and maximums, and listed by the `players`, through the catalog.
+    fn normalize(&self, players: &[Player]) -> (Vec<Player>, Constraints) {
+        let catalog = &self.positions;
+        let limits = |limits: &BTreeMap<String, usize>, merge: fn(usize, usize) -> usize| {
+            let mut normalized = BTreeMap::new();
+            for (pos, &limit) in limits {
+                normalized.entry(catalog.normalize(pos))
+                    .and_modify(|l| *l = merge(*l, limit))
+                    .or_insert(limit);
+            }
+            normalized
+        };
+        let constraints = Constraints {
+            min_positions: limits(&self.min_positions, usize::max),
+            max_positions: limits(&self.max_positions, usize::min),
+            shared_rules: SharedRules {
+                excluded_positions: self.shared_rules.excluded_positions.iter().map(|p| catalog.normalize(p)).collect(),
+                ..self.shared_rules.clone()
+            },
+            ..self.clone()
+        };
+        let players = players.iter().map(|p| catalog.normalize_player(p)).collect();
+        (players, constraints)
+    }
+}
+
+/// The letter a team is known by, `A` for the first team.
+pub fn team_label(team: usize) -> String {
+    char::from(b'A' + team as u8).to_string()
+}
+
 fn rating(team: &Team<'_>) -> f32 {
     let rating: f32 = team.players.iter().map(|p| p.rating).sum();
     rating + team.half_player.map(|p| p.rating).unwrap_or(0.0)
 }
 
-fn min_pos_met(team: &Vec<&Player>, min_positions: &BTreeMap<String, usize>) -> bool {
+/// Counts the players on a team who list a position, counting players who list several positions for each.
+fn position_count(team: &[&Player], pos: &str) -> usize {
+    team.iter().filter(|p| match &p.position {
+        Some(v) => v.iter().any(|p_pos| p_pos == pos),
+        None => false,
+    })
+    .count()
+}
+
+/// The standard deviation of a team's ratings, which is high for a few stars carrying weak teammates.
+pub fn rating_spread<'a>(players: impl IntoIterator<Item = &'a Player>) -> f32 {
+    let ratings = players.into_iter().map(|p| p.rating).collect::<Vec<_>>();
+    if ratings.is_empty() {
+        return 0.0;
+    }
+    let mean = ratings.iter().sum::<f32>() / ratings.len() as f32;
+    (ratings.iter().map(|r| (r - mean).powi(2)).sum::<f32>() / ratings.len() as f32).sqrt()
+}
+
+/// The last of the `top` highest rated players, with ties broken by name so exactly `top` players count.
+fn top_cutoff<'a>(players: impl IntoIterator<Item = &'a Player>, top: usize) -> Option<&'a Player> {
+    let mut players = players.into_iter().collect::<Vec<_>>();
+    players.sort_by(|a, b| b.rating.total_cmp(&a.rating).then_with(|| a.name.cmp(&b.name)));
+    top.checked_sub(1).and_then(|i| players.get(i).or(players.last()).copied())
+}
+
+/// Whether `p` is one of the top rated players ending at `cutoff`, from [`top_cutoff`].
+fn is_top(p: &Player, cutoff: &Player) -> bool {
+    p.rating.total_cmp(&cutoff.rating).then_with(|| cutoff.name.cmp(&p.name)).is_ge()
+}
+
+/// Counts how far the top rated players are from being split evenly between the teams.
+///
+/// The shared player plays for every team, so they don't count towards any team's share.
+fn top_split_violations(teams: &[Team<'_>], constraints: &Constraints) -> usize {
+    let roster = teams.iter().flat_map(|t| t.players.iter().copied()).chain(teams[0].half_player);
+    let Some(cutoff) = top_cutoff(roster, constraints.top_split) else {
+        return 0;
+    };
+    let counts = teams.iter()
+        .map(|t| t.players.iter().filter(|p| is_top(p, cutoff)).count())
+        .collect::<Vec<_>>();
+    let total: usize = counts.iter().sum();
+    let (fewest, most) = (total / teams.len(), total.div_ceil(teams.len()));
+    counts.iter()
+        .map(|&c| fewest.saturating_sub(c) + c.saturating_sub(most))
+        .sum()
+}
+
+/// Counts how many players a team is short of the position minimums.
+fn missing_positions(team: &[&Player], min_positions: &BTreeMap<String, usize>) -> usize {
     min_positions.iter()
-        .all(|(pos, req)| {
-            let pos_count = team.iter().filter(|p| match &p.position {
-                Some(v) => v.iter().any(|p_pos| p_pos == pos),
-                None => false,
+        .map(|(pos, req)| req.saturating_sub(position_count(team, pos)))
+        .sum()
+}
+
+/// Counts how far a split is over the position maximums, and from spreading positions evenly when asked to.
+fn position_spread_violations(teams: &[Team<'_>], constraints: &Constraints) -> usize {
+    let over: usize = teams.iter()
+        .flat_map(|t| constraints.max_positions.iter()
+            .map(|(pos, max)| position_count(&t.players, pos).saturating_sub(*max)))
+        .sum();
+    let uneven: usize = if constraints.even_positions {
+        // Every position the catalog knows, and any other one given a limit.
+        let positions = constraints.positions.positions.iter()
+            .map(|p| &p.name)
+            .chain(constraints.min_positions.keys())
+            .chain(constraints.max_positions.keys())
+            .collect::<BTreeSet<_>>();
+        positions.into_iter()
+            .map(|pos| {
+                let counts = teams.iter().map(|t| position_count(&t.players, pos));
+                let (min, max) = counts.fold((usize::MAX, 0), |(min, max), c| (min.min(c), max.max(c)));
+                (max - min).saturating_sub(1)
             })
-            .count();
-            pos_count >= *req
+            .sum()
+    } else {
+        0
+    };
+    over + uneven
+}
+
+/// Counts how far a split is from meeting the gender rules.
+fn gender_violations(teams: &[Team<'_>], constraints: &Constraints) -> usize {
+    let counts = teams.iter()
+        .map(|t| {
+            let women = t.players.iter().filter(|p| p.gender).count();
+            (women, t.players.len() - women)
+        })
+        .collect::<Vec<_>>();
+
+    let mut violations = counts.iter()
+        .map(|&(women, men)| constraints.min_per_gender.saturating_sub(women)
+            + constraints.min_per_gender.saturating_sub(men))
+        .sum();
+
+    if let Some(max_gender_delta) = constraints.max_gender_delta {
+        let spread = |count: fn(&(usize, usize)) -> usize| {
+            let max = counts.iter().map(count).max().unwrap_or(0);
+            let min = counts.iter().map(count).min().unwrap_or(0);
+            (max - min).saturating_sub(max_gender_delta)
+        };
+        violations += spread(|c| c.0).max(spread(|c| c.1));
+    }
+    violations
+}
+
+/// Counts the pairing rules a split breaks.
+///
+/// The shared player plays with every team, so they can't be kept together with or apart from anyone.
+fn pairing_violations(teams: &[Team<'_>], pairings: &[Pairing]) -> usize {
+    let team_of = |name: &str| teams.iter().position(|t| t.players.iter().any(|p| p.name == name));
+    let is_half = |name: &str| teams[0].half_player.is_some_and(|p| p.name == name);
+
+    pairings.iter()
+        .filter(|rule| {
+            // Rules about players who aren't here tonight don't apply.
+            let (first, second) = (team_of(&rule.first), team_of(&rule.second));
+            let present = |name: &str, team: Option<usize>| team.is_some() || is_half(name);
+            if !present(&rule.first, first) || !present(&rule.second, second) {
+                return false;
+            }
+            match (first, second) {
+                (Some(first), Some(second)) => (first == second) != rule.together,
+                _ => true,
+            }
+        })
+        .count()
+}
+
+/// The total of an attribute over a team, including the shared player like [`rating`] does.
+pub fn attribute_total<'a>(players: impl IntoIterator<Item = &'a Player>, attribute: &str) -> f32 {
+    players.into_iter().map(|p| p.attribute(attribute)).sum()
+}
+
+/// Counts how far a split is from balancing every attribute, one violation per whole rating point over tolerance.
+fn attribute_violations(teams: &[Team<'_>], constraints: &Constraints) -> usize {
+    constraints.attribute_tolerances.iter()
+        .map(|(attribute, &tolerance)| {
+            let (min, max) = teams.iter()
+                .map(|t| attribute_total(t.players.iter().copied().chain(t.half_player), attribute))
+                .fold((f32::MAX, f32::MIN), |(min, max), r| (min.min(r), max.max(r)));
+            let excess = max - min - tolerance;
+            if excess > 0.0 {
+                excess.ceil() as usize
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// How good a split is, compared field by field, lower is better.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+struct Score {
+    /// How many rules the split breaks
+    violations: usize,
+    /// How far the rating gap is over the max delta, so balance is never traded for variety
+    over_delta: f32,
+    /// The rating gap, plus the penalties for uneven depth, repeating past teammates and sharing the same player
+    cost: f32,
+}
+
+/// The rating each team is given for being larger than the smallest team, when uneven teams are compensated.
+///
+/// Balancing totals with this handicap makes the smaller team's own total beat the larger team's by the handicap.
+fn compensation(sizes: &[usize], constraints: &Constraints) -> Vec<f32> {
+    let smallest = sizes.iter().copied().min().unwrap_or(0);
+    let per_player = match constraints.odd_roster {
+        OddRoster::Uneven(per_player) => per_player,
+        _ => 0.0,
+    };
+    sizes.iter().map(|&size| (size - smallest) as f32 * per_player).collect()
+}
+
+/// The rating gap between the strongest and weakest team, including any handicap for having an extra player.
+fn gap(teams: &[Team<'_>], constraints: &Constraints) -> f32 {
+    let sizes = teams.iter().map(|t| t.players.len()).collect::<Vec<_>>();
+    let (min, max) = teams.iter()
+        .zip(compensation(&sizes, constraints))
+        .map(|(t, bonus)| rating(t) + bonus)
+        .fold((f32::MAX, f32::MIN), |(min, max), r| (min.min(r), max.max(r)));
+    max - min
+}
+
+/// The gap between the teams with the widest and narrowest spread of ratings.
+fn depth_gap(teams: &[Team<'_>]) -> f32 {
+    let (min, max) = teams.iter()
+        .map(|t| rating_spread(t.players.iter().copied()))
+        .fold((f32::MAX, f32::MIN), |(min, max), s| (min.min(s), max.max(s)));
+    max - min
+}
+
+/// For each team, how many of its players were on each team of a past session.
+fn overlaps(teams: &[Team<'_>], past: &BTreeMap<String, usize>) -> Vec<Vec<usize>> {
+    let past_teams = past.values().max().map_or(0, |t| t + 1);
+    teams.iter()
+        .map(|t| {
+            let mut counts = vec![0; past_teams];
+            for p in &t.players {
+                if let Some(&old) = past.get(&p.name) {
+                    counts[old] += 1;
+                }
+            }
+            counts
         })
+        .collect()
 }
 
-pub fn get_even_teams(players: &[Player], max_delta: f32, min_positions: &BTreeMap<String, usize>)  -> Result<(Vec<Player>, Vec<Player>), String> {
-    for _ in 0..100_000 {
-        let (a, b) = random_team(players);
-        // Check teams are close enough in skill
-        if ((rating(&a) - rating(&b)).abs() < max_delta) &&
-            // The shared player doesn't count for meeting the minimum position counts.
-            min_pos_met(&a.players, min_positions) &&
-            min_pos_met(&b.players, min_positions) &&
-            // Disallow GK as a shared player for now
-            (!a.half_player
-                .and_then(|p| p.position.as_ref())
-                .is_some_and(|pos| pos.iter().any(|p| p == "GK"))
-            )
-        {
-            return Ok((a.owned(1), b.owned(2)))
+/// How many players changed teams, pairing new teams with old ones the way that keeps the most players together.
+fn players_moved(overlaps: &[Vec<usize>]) -> usize {
+    let old_teams = overlaps.iter().map(Vec::len).max().unwrap_or(0);
+    // `stayed[used]` is the most players kept together by the teams so far, pairing with the old teams in the `used` bitmask.
+    let mut stayed = vec![None; 1 << old_teams];
+    stayed[0] = Some(0);
+    for counts in overlaps {
+        // A new team can also pair with no old team, when there are more teams than before.
+        let mut next = stayed.clone();
+        for (used, kept) in stayed.iter().enumerate() {
+            let Some(kept) = kept else {
+                continue;
+            };
+            for (o, &c) in counts.iter().enumerate().filter(|&(o, _)| used & (1 << o) == 0) {
+                let with = used | (1 << o);
+                next[with] = next[with].max(Some(kept + c));
+            }
         }
+        stayed = next;
     }
-    Err(format!("Unable to generate even teams.  You may need to loosen the requirements for each team."))
+    overlaps.iter().flatten().sum::<usize>() - stayed.into_iter().flatten().max().unwrap_or(0)
 }
 
+/// Weighs how often this split's teammates played together before, halving the weight for each older session.
+fn repeat_penalty(teams: &[Team<'_>], constraints: &Constraints) -> f32 {
+    if constraints.repeat_penalty == 0.0 {
+        return 0.0;
+    }
+    constraints.past_teams.iter()
+        .enumerate()
+        .map(|(age, past)| {
+            let pairs: usize = overlaps(teams, past).iter()
+                .flatten()
+                .map(|&c| c * c.saturating_sub(1) / 2)
+                .sum();
+            pairs as f32 * 0.5f32.powi(age as i32)
+        })
+        .sum::<f32>() * constraints.repeat_penalty
+}
 
-pub fn random_team<'a>(players: &'a [Player]) -> (Team<'a>, Team<'a>) {
+/// Weighs how often the shared player has been shared before, so everyone eligible takes a turn.
+fn shared_penalty(teams: &[Team<'_>], constraints: &Constraints) -> f32 {
+    teams[0].half_player
+        .and_then(|p| constraints.past_shared.get(&p.name))
+        .map_or(0.0, |&times| times as f32 * constraints.shared_repeat_penalty)
+}
 
-    let player_count = players.len();
+fn score(teams: &[Team<'_>], constraints: &Constraints) -> Score {
+    // The shared player doesn't count for meeting the minimum position or gender counts.
+    let mut violations: usize = teams.iter()
+        .map(|t| missing_positions(&t.players, &constraints.min_positions))
+        .sum();
+    violations += position_spread_violations(teams, constraints);
+    violations += gender_violations(teams, constraints);
+    violations += pairing_violations(teams, &constraints.pairings);
+    violations += attribute_violations(teams, constraints);
+    violations += top_split_violations(teams, constraints);
+    if teams[0].half_player.is_some_and(|p| !constraints.shared_rules.allows(p)) {
+        violations += 1;
+    }
+    if let Some(last) = constraints.past_teams.first() {
+        violations += constraints.min_changes.saturating_sub(players_moved(&overlaps(teams, last)));
+    }
+
+    let gap = gap(teams, constraints);
+    Score {
+        violations,
+        over_delta: (gap - constraints.max_delta).max(0.0),
+        cost: gap
+            + constraints.depth_weight * depth_gap(teams)
+            + repeat_penalty(teams, constraints)
+            + shared_penalty(teams, constraints),
+    }
+}
+
+fn is_better(x: Score, y: Score) -> bool {
+    x < y
+}
+
+/// Exchanges player `i` of team `t` with player `j` of team `u`, unless either is locked.
+fn swap_players(teams: &mut [Team<'_>], t: usize, i: usize, u: usize, j: usize) -> bool {
+    if teams[t].players[i].fixed_team.is_some() || teams[u].players[j].fixed_team.is_some() {
+        return false;
+    }
+    let (left, right) = teams.split_at_mut(u);
+    std::mem::swap(&mut left[t].players[i], &mut right[0].players[j]);
+    true
+}
+
+/// Exchanges the shared player with player `i` of team `t`, unless that player is locked.
+fn swap_half(teams: &mut [Team<'_>], t: usize, i: usize) -> bool {
+    let (Some(half), current) = (teams[t].half_player, teams[t].players[i]) else {
+        return false;
+    };
+    if current.fixed_team.is_some() {
+        return false;
+    }
+    teams[t].players[i] = half;
+    for team in teams.iter_mut() {
+        team.half_player = Some(current);
+    }
+    true
+}
+
+/// Improves a split by swapping unlocked players until no single swap helps.
+fn local_search(teams: &mut [Team<'_>], constraints: &Constraints) -> Score {
+    let mut best = score(teams, constraints);
+    loop {
+        let mut improved = false;
+
+        for t in 0..teams.len() {
+            for u in t + 1..teams.len() {
+                for i in 0..teams[t].players.len() {
+                    for j in 0..teams[u].players.len() {
+                        if !swap_players(teams, t, i, u, j) {
+                            continue;
+                        }
+                        let candidate = score(teams, constraints);
+                        if is_better(candidate, best) {
+                            best = candidate;
+                            improved = true;
+                        } else {
+                            swap_players(teams, t, i, u, j);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Try trading the shared player with someone from any team.
+        if teams[0].half_player.is_some() {
+            for t in 0..teams.len() {
+                for i in 0..teams[t].players.len() {
+                    if !swap_half(teams, t, i) {
+                        continue;
+                    }
+                    let candidate = score(teams, constraints);
+                    if is_better(candidate, best) {
+                        best = candidate;
+                        improved = true;
+                    } else {
+                        swap_half(teams, t, i);
+                    }
+                }
+            }
+        }
+
+        if !improved {
+            return best;
+        }
+    }
+}
+
+/// How many players each team gets, and whether a leftover player is shared between the teams.
+///
+/// Teams are the same size, except that a single leftover player is shared as the `odd_roster` policy says,
+/// and otherwise leftover players make the first few teams one player larger.
+fn team_sizes(player_count: usize, num_teams: usize, odd_roster: OddRoster) -> (Vec<usize>, bool) {
+    let mut sizes = vec![player_count / num_teams; num_teams];
+    let leftover = player_count % num_teams;
+    let shared = leftover == 1 && match odd_roster {
+        OddRoster::Shared => num_teams == 2,
+        OddRoster::Uneven(_) => false,
+        OddRoster::Substitute => true,
+    };
+    if !shared {
+        for size in sizes.iter_mut().take(leftover) {
+            *size += 1;
+        }
+    }
+    (sizes, shared)
+}
 
-    let mut team_a = players.iter()
-        .filter(|p| p.fixed_team.is_some_and(|f| f))
+/// Explains every reason the constraints can't be met by any split, before searching for one.
+pub fn diagnose(players: &[Player], num_teams: usize, constraints: &Constraints) -> Vec<String> {
+    let (players, constraints) = constraints.normalize(players);
+    let (players, constraints) = (&players[..], &constraints);
+    if players.is_empty() {
+        return vec!["There are no players to split into teams.".to_string()];
+    }
+    if num_teams < 2 {
+        return vec!["At least two teams are needed.".to_string()];
+    }
+
+    let mut problems = vec![];
+    for p in players.iter().filter(|p| p.fixed_team.is_some_and(|t| t >= num_teams)) {
+        problems.push(format!(
+            "{} is locked to team {}, but only {num_teams} teams are being made.",
+            p.name,
+            team_label(p.fixed_team.unwrap()),
+        ));
+    }
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    let (sizes, shared) = team_sizes(players.len(), num_teams, constraints.odd_roster);
+    let locked = (0..num_teams)
+        .map(|t| players.iter().filter(|p| p.fixed_team == Some(t)).collect::<Vec<_>>())
         .collect::<Vec<_>>();
+    let free = players.iter().filter(|p| p.fixed_team.is_none()).collect::<Vec<_>>();
+
+    for (t, team) in locked.iter().enumerate() {
+        if team.len() > sizes[t] {
+            problems.push(format!(
+                "Team {} locks {} players, which already exceeds its share of {} from a roster of {}.",
+                team_label(t),
+                team.len(),
+                sizes[t],
+                players.len(),
+            ));
+        }
+    }
+
+    let lists = |p: &Player, pos: &str| p.position.as_ref().is_some_and(|v| v.iter().any(|p_pos| p_pos == pos));
+    for (pos, &req) in constraints.min_positions.iter().filter(|(_, &req)| req > 0) {
+        let needed = req * num_teams;
+        let listed = players.iter().filter(|p| lists(p, pos)).count();
+        let shortfall: usize = locked.iter()
+            .map(|team| req.saturating_sub(team.iter().filter(|p| lists(p, pos)).count()))
+            .sum();
+        let free_listed = free.iter().filter(|p| lists(p, pos)).count();
+        if listed < needed {
+            problems.push(format!(
+                "Only {listed} player{} list{} {pos}, but Min {} is {req} per team ({needed} needed).",
+                if listed == 1 { "" } else { "s" },
+                if listed == 1 { "s" } else { "" },
+                pos.to_uppercase(),
+            ));
+        } else if free_listed < shortfall {
+            problems.push(format!(
+                "Only {free_listed} unlocked players list {pos}, but the teams still need {shortfall} more to reach Min {} after their locked players.",
+                pos.to_uppercase(),
+            ));
+        }
+    }
+
+    for (pos, &max) in &constraints.max_positions {
+        let min = constraints.min_positions.get(pos).copied().unwrap_or(0);
+        let listed = players.iter().filter(|p| lists(p, pos)).count();
+        if max < min {
+            problems.push(format!(
+                "Max {} is {max} per team, which is below Min {} of {min}.",
+                pos.to_uppercase(),
+                pos.to_uppercase(),
+            ));
+        } else if listed > max * num_teams + usize::from(shared) {
+            problems.push(format!(
+                "{listed} players list {pos}, but Max {} is {max} per team (only {} fit).",
+                pos.to_uppercase(),
+                max * num_teams,
+            ));
+        }
+        for (t, team) in locked.iter().enumerate() {
+            let locked_listed = team.iter().filter(|p| lists(p, pos)).count();
+            if locked_listed > max {
+                problems.push(format!(
+                    "Team {} locks {locked_listed} players who list {pos}, over Max {} of {max}.",
+                    team_label(t),
+                    pos.to_uppercase(),
+                ));
+            }
+        }
+    }
+
+    let women = players.iter().filter(|p| p.gender).count();
+    let men = players.len() - women;
+    let genders = [
+        (women, if women == 1 { "woman" } else { "women" }),
+        (men, if men == 1 { "man" } else { "men" }),
+    ];
+    let min = constraints.min_per_gender;
+    if min > 0 {
+        for (count, gender) in genders {
+            if count < min * num_teams {
+                problems.push(format!(
+                    "There {} only {count} {gender}, but the mixed league needs {min} per team ({} needed).",
+                    if count == 1 { "is" } else { "are" },
+                    min * num_teams,
+                ));
+            }
+        }
+        if let Some(&smallest) = sizes.iter().min() {
+            if smallest < 2 * min {
+                problems.push(format!(
+                    "Teams of {smallest} can't fit {min} women and {min} men each.",
+                ));
+            }
+        }
+    }
+    if constraints.max_gender_delta == Some(0) {
+        for (count, gender) in genders {
+            // The shared player isn't counted on any team, so they can take up the one left over.
+            let even = |count: usize| count.is_multiple_of(num_teams);
+            if !(even(count) || shared && even(count - 1)) {
+                problems.push(format!(
+                    "{count} {gender} can't be split evenly across {num_teams} teams, so a gender difference of 0 is impossible.",
+                ));
+            }
+        }
+    }
+
+    if constraints.top_split > players.len() {
+        problems.push(format!(
+            "The top {} players can't be split between teams, because there are only {} players.",
+            constraints.top_split,
+            players.len(),
+        ));
+    } else if let Some(cutoff) = top_cutoff(players, constraints.top_split) {
+        let top = players.iter().filter(|p| is_top(p, cutoff)).count();
+        let most = top.div_ceil(num_teams);
+        for (t, team) in locked.iter().enumerate() {
+            let locked_top = team.iter().filter(|p| is_top(p, cutoff)).count();
+            if locked_top > most {
+                problems.push(format!(
+                    "Team {} locks {locked_top} of the top {top} rated players, but an even split gives each team at most {most}.",
+                    team_label(t),
+                ));
+            }
+        }
+    }
 
-    let mut team_b = players.iter()
-        .filter(|p| p.fixed_team.is_some_and(|f| !f))
+    if shared && !free.iter().any(|p| constraints.shared_rules.allows(p)) {
+        problems.push(format!(
+            "With {} players one has to be shared between the teams, but the shared player rules exclude every unlocked player.",
+            players.len(),
+        ));
+    }
+
+    problems.extend(pairing_problems(players, &locked, &sizes, constraints));
+
+    if let Some(last) = constraints.past_teams.first() {
+        let movable = free.iter().filter(|p| last.contains_key(&p.name)).count();
+        if constraints.min_changes > movable {
+            problems.push(format!(
+                "{} players can't change teams since the last game, because only {movable} unlocked players played in it.",
+                constraints.min_changes,
+            ));
+        }
+    }
+
+    // No pair of teams can get closer than their locked players plus the worst and best the unlocked players could add.
+    // The shared player counts for both teams, so they don't change the gap.
+    let bonus = compensation(&sizes, constraints);
+    let mut free_ratings = free.iter().map(|p| p.rating).collect::<Vec<_>>();
+    free_ratings.sort_by(|a, b| a.total_cmp(b));
+    let locked_ratings = locked.iter()
+        .map(|team| team.iter().map(|p| p.rating).sum::<f32>())
+        .collect::<Vec<_>>();
+    let open = |t: usize| sizes[t].saturating_sub(locked[t].len()).min(free_ratings.len());
+    let mut worst_gap: Option<(f32, usize, usize)> = None;
+    for t in 0..num_teams {
+        for u in 0..num_teams {
+            if t == u {
+                continue;
+            }
+            let lowest_t = locked_ratings[t] + bonus[t] + free_ratings[..open(t)].iter().sum::<f32>();
+            let highest_u = locked_ratings[u] + bonus[u] + free_ratings[free_ratings.len() - open(u)..].iter().sum::<f32>();
+            let gap = lowest_t - highest_u;
+            if gap >= constraints.max_delta && worst_gap.is_none_or(|(worst, _, _)| gap > worst) {
+                worst_gap = Some((gap, t, u));
+            }
+        }
+    }
+    if let Some((gap, t, u)) = worst_gap {
+        problems.push(format!(
+            "A delta of {} is impossible because, given their locked players and sizes, team {} ends up at least {gap} ahead of team {}.",
+            constraints.max_delta,
+            team_label(t),
+            team_label(u),
+        ));
+    }
+
+    problems
+}
+
+/// Explains pairing rules that contradict each other, the team locks, or the team sizes.
+fn pairing_problems(players: &[Player], locked: &[Vec<&Player>], sizes: &[usize], constraints: &Constraints) -> Vec<String> {
+    let index = |name: &str| players.iter().position(|p| p.name == name);
+    let pairings = constraints.pairings.iter()
+        .chain(players.iter().flat_map(|p| &p.pairings))
+        .filter_map(|rule| {
+            let (a, b) = (index(&rule.first)?, index(&rule.second)?);
+            Some((a.min(b), a.max(b), rule.together))
+        })
+        // Both players may list the same rule on the sheet.
+        .collect::<BTreeSet<_>>();
+
+    // Group everyone who has to play together.
+    let mut group = (0..players.len()).collect::<Vec<_>>();
+    fn root(group: &mut [usize], i: usize) -> usize {
+        if group[i] != i {
+            group[i] = root(group, group[i]);
+        }
+        group[i]
+    }
+    for &(a, b, together) in &pairings {
+        if together {
+            let (a, b) = (root(&mut group, a), root(&mut group, b));
+            group[a] = b;
+        }
+    }
+
+    let mut problems = vec![];
+    for &(a, b, together) in &pairings {
+        if !together && root(&mut group, a) == root(&mut group, b) {
+            problems.push(format!(
+                "{} and {} have to play apart, but other rules put them on the same team.",
+                players[a].name,
+                players[b].name,
+            ));
+        }
+        if !together && players[a].fixed_team.is_some() && players[a].fixed_team == players[b].fixed_team {
+            problems.push(format!(
+                "{} and {} have to play apart, but are both locked to team {}.",
+                players[a].name,
+                players[b].name,
+                team_label(players[a].fixed_team.unwrap()),
+            ));
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..players.len() {
+        let r = root(&mut group, i);
+        groups.entry(r).or_default().push(i);
+    }
+    let largest_team = sizes.iter().copied().max().unwrap_or(0);
+    for members in groups.values().filter(|m| m.len() > 1) {
+        let names = members.iter().map(|&i| players[i].name.as_str()).collect::<Vec<_>>().join(", ");
+        let teams = members.iter()
+            .filter_map(|&i| players[i].fixed_team)
+            .collect::<BTreeSet<_>>();
+        if teams.len() > 1 {
+            problems.push(format!(
+                "{names} have to play together, but are locked to teams {}.",
+                teams.iter().map(|&t| team_label(t)).collect::<Vec<_>>().join(" and "),
+            ));
+        } else if let Some(&t) = teams.iter().next() {
+            let joining = members.iter().filter(|&&i| players[i].fixed_team.is_none()).count();
+            if locked[t].len() + joining > sizes[t] {
+                problems.push(format!(
+                    "{names} have to play together on team {}, but it only has room for {}.",
+                    team_label(t),
+                    sizes[t],
+                ));
+            }
+        } else if members.len() > largest_team {
+            problems.push(format!(
+                "{names} have to play together, but teams only have {largest_team} players.",
+            ));
+        }
+    }
+    problems
+}
+
+/// Searches for the most even splits into `num_teams` teams, always returning the best `count` distinct ones found, best first.
+///
+/// The search does the same amount of work on every device, so passing the `seed` reported by a previous run replays
+/// those exact splits. Small rosters are then searched exhaustively, which proves the splits found are the best there are.
+/// Once the `budget` is spent, the best splits found so far are returned as incomplete, since their seed may not replay them.
+/// Constraints that can't be met still get the closest splits, with the reasons from [`diagnose`] as warnings.
+///
+/// `progress` is called now and then with how far along the search is, from 0 to 1.
+pub fn get_even_teams(
+    players: &[Player],
+    num_teams: usize,
+    constraints: &Constraints,
+    count: usize,
+    seed: Option<u64>,
+    budget: Duration,
+    mut progress: impl FnMut(f32),
+) -> Result<Vec<GeneratedTeams>, String> {
+    let (players, constraints) = constraints.normalize(players);
+    let (players, constraints) = (&players[..], &constraints);
+    // Without players or teams there's nothing to split, but any other problem still gets the closest split.
+    let mut warnings = diagnose(players, num_teams, constraints);
+    if players.is_empty() || num_teams < 2 {
+        return Err(warnings.join("\n"));
+    }
+    // Players locked to a team that isn't being made are split like everyone else,
+    // which `diagnose` stops at, so the rest of the roster is checked again without those locks.
+    let unlocked = players.iter()
+        .map(|p| Player { fixed_team: p.fixed_team.filter(|&t| t < num_teams), ..p.clone() })
+        .collect::<Vec<_>>();
+    if players.iter().any(|p| p.fixed_team.is_some_and(|t| t >= num_teams)) {
+        warnings.extend(diagnose(&unlocked, num_teams, constraints));
+    }
+    let players = &unlocked[..];
+
+    // Pairings listed on the sheet count the same as ones added in the app.
+    let mut constraints = constraints.clone();
+    constraints.pairings.extend(players.iter().flat_map(|p| p.pairings.iter().cloned()));
+    let constraints = &constraints;
+
+    let deadline = Instant::now() + budget;
+    let exhaustive = players.len() <= exact::MAX_PLAYERS;
+
+    let seed = seed.unwrap_or_else(|| thread_rng().gen::<u32>().into());
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    let count = count.max(1);
+    let mut best: Vec<Candidate<'_>> = vec![];
+    let mut optimal = false;
+    let mut complete = true;
+    for restart in 0..RESTARTS {
+        // At least one split is always found, however short the budget.
+        if restart > 0 && Instant::now() > deadline {
+            complete = false;
+            break;
+        }
+        let done = restart as f32 / RESTARTS as f32;
+        progress(if exhaustive { done / 2.0 } else { done });
+
+        let mut teams = random_team(players, num_teams, constraints.odd_roster, &mut rng);
+        let score = local_search(&mut teams, constraints);
+        let key = split_key(players, &teams);
+        if best.iter().any(|(_, k, _)| *k == key) {
+            continue;
+        }
+        let rank = Rank { score, delta: gap(&teams, constraints), slack: slack(&teams, constraints) };
+        best.push((rank, key, teams));
+        best.sort_by_key(|(rank, _, _)| *rank);
+        best.truncate(count);
+        // Nothing can beat perfectly even, fresh splits that meet every rule.
+        if best.len() == count && best.iter().all(|(r, _, _)| r.score.violations == 0 && r.score.cost == 0.0) {
+            optimal = true;
+            break;
+        }
+    }
+    // The exhaustive search needs every team to have room for its locked players.
+    if complete && !optimal && exhaustive && warnings.is_empty() {
+        match exact::improve(players, num_teams, constraints, count, &mut best, deadline, &mut |done| progress(0.5 + done / 2.0)) {
+            Some(proven) => optimal = proven,
+            None => complete = false,
+        }
+    }
+
+    Ok(best.into_iter()
+        .map(|(rank, _, teams)| GeneratedTeams {
+            teams: teams.iter()
+                .map(Team::owned)
+                .collect(),
+            shared: teams[0].half_player.cloned(),
+            delta: rank.delta,
+            constraints_met: warnings.is_empty() && rank.score.violations == 0 && rank.delta < constraints.max_delta,
+            seed,
+            optimal,
+            complete,
+            warnings: warnings.clone(),
+        })
+        .collect())
+}
+
+/// A split kept by the search, with its rank and [`split_key`].
+type Candidate<'a> = (Rank, Vec<Vec<usize>>, Vec<Team<'a>>);
+
+/// How a candidate split compares to others, best first.
+#[derive(Clone, Copy, PartialEq)]
+struct Rank {
+    score: Score,
+    delta: f32,
+    slack: usize,
+}
+
+impl Eq for Rank {}
+
+impl PartialOrd for Rank {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rank {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score.violations.cmp(&other.score.violations)
+            .then(self.score.over_delta.total_cmp(&other.score.over_delta))
+            .then(self.score.cost.total_cmp(&other.score.cost))
+            // More room to spare on the minimums is better.
+            .then(other.slack.cmp(&self.slack))
+    }
+}
+
+/// The smallest margin any team has over a position or gender minimum.
+fn slack(teams: &[Team<'_>], constraints: &Constraints) -> usize {
+    teams.iter()
+        .flat_map(|t| {
+            let positions = constraints.min_positions.iter()
+                .filter(|(_, &req)| req > 0)
+                .map(|(pos, req)| position_count(&t.players, pos).saturating_sub(*req));
+            let women = t.players.iter().filter(|p| p.gender).count();
+            let genders = (constraints.min_per_gender > 0)
+                .then(|| [women, t.players.len() - women].map(|c| c.saturating_sub(constraints.min_per_gender)))
+                .into_iter()
+                .flatten();
+            positions.chain(genders).collect::<Vec<_>>()
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+/// Identifies a split regardless of the order of the teams, so mirror images of a split compare equal.
+fn split_key(players: &[Player], teams: &[Team<'_>]) -> Vec<Vec<usize>> {
+    let index = |p: &Player| players.iter().position(|q| std::ptr::eq(p, q)).unwrap();
+    let mut key = teams.iter()
+        .map(|t| {
+            let mut team = t.players.iter().map(|&p| index(p)).collect::<Vec<_>>();
+            team.sort();
+            team
+        })
+        .collect::<Vec<_>>();
+    key.sort();
+    if let Some(p) = teams[0].half_player {
+        key.push(vec![index(p)]);
+    }
+    key
+}
+
+
+/// Randomly deals the unlocked players out to `num_teams` teams, sized by [`team_sizes`].
+pub fn random_team<'a>(players: &'a [Player], num_teams: usize, odd_roster: OddRoster, rng: &mut impl Rng) -> Vec<Team<'a>> {
+
+    let (team_sizes, shared) = team_sizes(players.len(), num_teams, odd_roster);
+
+    let mut teams = (0..num_teams)
+        .map(|t| players.iter()
+            .filter(|p| p.fixed_team == Some(t))
+            .collect::<Vec<_>>())
         .collect::<Vec<_>>();
 
     let mut players = players.iter()
         .filter(|p| p.fixed_team.is_none())
         .collect::<Vec<_>>();
 
-    players.shuffle(&mut thread_rng());
-    
-    while team_a.len() < player_count / 2 {
-        team_a.push(players.pop().unwrap())
+    players.shuffle(rng);
+
+    // The one player left over from an odd roster is shared, unless the teams are uneven.
+    let half_player = if shared { players.pop() } else { None };
+
+    for (team, &size) in teams.iter_mut().zip(&team_sizes) {
+        while team.len() < size {
+            match players.pop() {
+                Some(p) => team.push(p),
+                None => break,
+            }
+        }
+    }
+    // Teams with more locked players than their share leave players over, who go to the smallest teams.
+    for p in players {
+        if let Some(team) = teams.iter_mut().min_by_key(|t| t.len()) {
+            team.push(p);
+        }
     }
 
-    while team_b.len() < player_count / 2 {
-        team_b.push(players.pop().unwrap())
+    teams.into_iter()
+        .map(|players| Team { players, half_player })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The most players kept together by pairing each new team with a different old team, or with none, trying every way.
+    fn most_kept(overlaps: &[Vec<usize>], used: &mut Vec<bool>) -> usize {
+        let Some((counts, rest)) = overlaps.split_first() else {
+            return 0;
+        };
+        let mut most = most_kept(rest, used);
+        for (o, &c) in counts.iter().enumerate() {
+            if !used[o] {
+                used[o] = true;
+                most = most.max(c + most_kept(rest, used));
+                used[o] = false;
+            }
+        }
+        most
     }
 
-    
-    let half_player = players.pop();
-    (
-        Team { players: team_a, half_player },
-        Team { players: team_b, half_player }
-    )
-}
\ No newline at end of file
+    #[test]
+    fn counts_moved_players_with_the_best_pairing_of_teams() {
+        // Mirrored teams move nobody.
+        assert_eq!(players_moved(&[vec![0, 5], vec![5, 0]]), 0);
+        // Pairing team by team would move 7, but pairing each with the other old team moves 1.
+        assert_eq!(players_moved(&[vec![1, 4], vec![3, 0]]), 1);
+        // A third team has no old team to pair with, so all of its players moved.
+        assert_eq!(players_moved(&[vec![4, 0], vec![0, 4], vec![1, 1]]), 2);
+        // Two new teams can't both pair with the same old team.
+        assert_eq!(players_moved(&[vec![3, 0, 0], vec![3, 0, 0]]), 3);
+        assert_eq!(players_moved(&[]), 0);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        for _ in 0..200 {
+            let old_teams = rng.gen_range(1..=4);
+            let overlaps = (0..rng.gen_range(1..=5))
+                .map(|_| (0..old_teams).map(|_| rng.gen_range(0..5)).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            let total = overlaps.iter().flatten().sum::<usize>();
+            assert_eq!(players_moved(&overlaps), total - most_kept(&overlaps, &mut vec![false; old_teams]), "{overlaps:?}");
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use super::*;
+
+/// The most players an adjustment moves between teams, besides those arriving or leaving.
+const MAX_MOVES: usize = 3;
+
+/// Where a player plays in a split.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Side {
+    Team(usize),
+    /// Split between the teams
+    Shared,
+}
+
+/// A player who changes sides when teams are adjusted.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Move {
+    pub name: String,
+    /// Where they played before, or `None` if they just arrived
+    pub from: Option<Side>,
+    /// Where they play now, or `None` if they left
+    pub to: Option<Side>,
+}
+
+/// Teams rebalanced around players arriving or leaving.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Adjustment {
+    pub teams: Vec<Vec<Player>>,
+    pub shared: Option<Player>,
+    /// The measured rating gap between the strongest and weakest team
+    pub delta: f32,
+    /// Whether the adjusted teams meet every requested constraint
+    pub constraints_met: bool,
+    /// Everyone who arrived, left or changed sides
+    pub moves: Vec<Move>,
+}
+
+/// Adds the `arrivals` to the current `teams` and takes out the `departures`,
+/// then moves as few players as it takes to get the teams back within the constraints.
+///
+/// If no adjustment of up to a few moves meets every constraint, the closest one is returned.
+pub fn adjust_teams(
+    teams: &[Vec<Player>],
+    shared: Option<&Player>,
+    arrivals: &[Player],
+    departures: &[String],
+    constraints: &Constraints,
+) -> Result<Adjustment, String> {
+    let num_teams = teams.len();
+    if num_teams < 2 {
+        return Err("Generate some teams before adjusting them.".to_string());
+    }
+
+    let current = teams.iter()
+        .enumerate()
+        .flat_map(|(t, team)| team.iter().map(move |p| (p, Side::Team(t))))
+        .chain(shared.map(|p| (p, Side::Shared)))
+        .collect::<Vec<_>>();
+    let mut moves = vec![];
+    for name in departures {
+        match current.iter().find(|(p, _)| &p.name == name) {
+            Some(&(_, side)) => moves.push(Move { name: name.clone(), from: Some(side), to: None }),
+            None => return Err(format!("{name} isn't on any team, so can't leave.")),
+        }
+    }
+    for p in arrivals {
+        if current.iter().any(|(q, _)| q.name == p.name) {
+            return Err(format!("{} is already on a team.", p.name));
+        }
+    }
+
+    // Everyone staying, then everyone arriving.
+    let (roster, start): (Vec<Player>, Vec<Option<Side>>) = current.iter()
+        .filter(|(p, _)| !departures.contains(&p.name))
+        .map(|&(p, side)| (p.clone(), Some(side)))
+        .chain(arrivals.iter().map(|p| (p.clone(), None)))
+        .unzip();
+    if roster.len() < num_teams {
+        return Err(format!("{} players can't make {num_teams} teams.", roster.len()));
+    }
+
+    let (roster, mut constraints) = constraints.normalize(&roster);
+    constraints.pairings.extend(roster.iter().flat_map(|p| p.pairings.iter().cloned()));
+
+    let (mut sizes, shared) = team_sizes(roster.len(), num_teams, constraints.odd_roster);
+    sizes.sort();
+    let mut search = Search {
+        roster: &roster,
+        start: &start,
+        constraints: &constraints,
+        sizes,
+        shared,
+        sides: vec![Side::Shared; roster.len()],
+        counts: vec![0; num_teams + 1],
+        best: None,
+    };
+    for allowed in 0..=MAX_MOVES {
+        search.place(0, allowed);
+        if search.best.as_ref().is_some_and(|(rank, _)| rank.0.violations == 0 && rank.0.over_delta == 0.0) {
+            break;
+        }
+    }
+    let Some((rank, sides)) = search.best else {
+        return Err("No adjustment of a few moves keeps the teams the right sizes.".to_string());
+    };
+
+    moves.extend(roster.iter()
+        .zip(&start)
+        .zip(&sides)
+        .filter(|((_, from), to)| **from != Some(**to))
+        .map(|((p, &from), &to)| Move { name: p.name.clone(), from, to: Some(to) }));
+
+    let teams = (0..num_teams)
+        .map(|t| roster.iter()
+            .zip(&sides)
+            .filter(|(_, &side)| side == Side::Team(t))
+            .map(|(p, _)| p.clone())
+            .collect())
+        .collect();
+    let shared = roster.iter()
+        .zip(&sides)
+        .find(|(_, &side)| side == Side::Shared)
+        .map(|(p, _)| p.clone());
+    Ok(Adjustment {
+        teams,
+        shared,
+        delta: rank.2,
+        constraints_met: rank.0.violations == 0 && rank.2 < constraints.max_delta,
+        moves,
+    })
+}
+
+/// Balance first, then the fewest moves, then the smallest cost.
+type AdjustRank = (Score, usize, f32);
+
+/// Tries every way to place the arriving players and move up to a given number of the others.
+struct Search<'a> {
+    roster: &'a [Player],
+    start: &'a [Option<Side>],
+    constraints: &'a Constraints,
+    /// The team sizes the roster splits into, smallest first
+    sizes: Vec<usize>,
+    shared: bool,
+    /// Where each player is placed so far
+    sides: Vec<Side>,
+    /// How many players are placed on each team, with the shared player last
+    counts: Vec<usize>,
+    best: Option<(AdjustRank, Vec<Side>)>,
+}
+
+impl Search<'_> {
+    fn slot(&self, side: Side) -> usize {
+        match side {
+            Side::Team(t) => t,
+            Side::Shared => self.counts.len() - 1,
+        }
+    }
+
+    /// Places player `i` onwards, moving at most `allowed` more players from where they started.
+    fn place(&mut self, i: usize, allowed: usize) {
+        if i == self.roster.len() {
+            self.evaluate();
+            return;
+        }
+        let num_teams = self.counts.len() - 1;
+        let largest = self.sizes.last().copied().unwrap_or(0);
+        let player = &self.roster[i];
+        let options = (0..num_teams)
+            .map(Side::Team)
+            .chain(self.shared.then_some(Side::Shared))
+            .filter(|&side| match player.fixed_team {
+                Some(t) => side == Side::Team(t),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+
+        for side in options {
+            let cost = usize::from(self.start[i].is_some_and(|from| from != side));
+            let slot = self.slot(side);
+            let full = match side {
+                Side::Team(_) => self.counts[slot] >= largest,
+                Side::Shared => self.counts[slot] >= 1,
+            };
+            if cost > allowed || full {
+                continue;
+            }
+            self.sides[i] = side;
+            self.counts[slot] += 1;
+            self.place(i + 1, allowed - cost);
+            self.counts[slot] -= 1;
+        }
+    }
+
+    /// Keeps a complete placement if it's the right sizes and better than the best so far.
+    fn evaluate(&mut self) {
+        let num_teams = self.counts.len() - 1;
+        let mut sizes = self.counts[..num_teams].to_vec();
+        sizes.sort();
+        if sizes != self.sizes || self.counts[num_teams] != usize::from(self.shared) {
+            return;
+        }
+
+        let half_player = self.roster.iter()
+            .zip(&self.sides)
+            .find(|(_, &side)| side == Side::Shared)
+            .map(|(p, _)| p);
+        let teams = (0..num_teams)
+            .map(|t| Team {
+                players: self.roster.iter()
+                    .zip(&self.sides)
+                    .filter(|(_, &side)| side == Side::Team(t))
+                    .map(|(p, _)| p)
+                    .collect(),
+                half_player,
+            })
+            .collect::<Vec<_>>();
+
+        let moved = self.start.iter()
+            .zip(&self.sides)
+            .filter(|(from, &to)| from.is_some_and(|from| from != to))
+            .count();
+        let rank = (score(&teams, self.constraints), moved, gap(&teams, self.constraints));
+        let better = match &self.best {
+            Some((best, _)) => rank.0.violations.cmp(&best.0.violations)
+                .then(rank.0.over_delta.total_cmp(&best.0.over_delta))
+                .then(rank.1.cmp(&best.1))
+                .then(rank.0.cost.total_cmp(&best.0.cost))
+                .is_lt(),
+            None => true,
+        };
+        if better {
+            self.best = Some((rank, self.sides.clone()));
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use super::*;
+
+/// Rosters up to this size are searched exhaustively after the local search.
+pub(super) const MAX_PLAYERS: usize = 26;
+
+/// How many partial splits the exhaustive search visits before giving up on proving its result.
+/// This is a count rather than a time, so a seed finds the same splits however fast the device is.
+const NODE_BUDGET: usize = 2_000_000;
+
+/// How many partial splits are visited between checking the deadline and reporting progress.
+const CHECK_EVERY: usize = 1 << 14;
+
+/// A branch and bound search over every way to deal the unlocked players out, keeping the best `count` splits.
+struct Search<'a, 'c> {
+    constraints: &'c Constraints,
+    deadline: Instant,
+    /// Called with the share of the node budget used so far
+    progress: &'c mut dyn FnMut(f32),
+    out_of_time: bool,
+    players: &'a [Player],
+    /// The unlocked players, highest rated first, so the bound tightens quickly
+    free: Vec<&'a Player>,
+    /// `suffix[i]` is the total rating of `free[i..]`
+    suffix: Vec<f32>,
+    sizes: Vec<usize>,
+    /// The handicap each team is given for its size, from [`compensation`]
+    bonus: Vec<f32>,
+    shared: bool,
+    /// The last player to count among the top rated for [`Constraints::top_split`]
+    top_cutoff: Option<&'a Player>,
+    count: usize,
+    best: Vec<Candidate<'a>>,
+    nodes: usize,
+}
+
+/// Searches every split for one that scores better than those in `best`, replacing them with the best `count` splits there are.
+///
+/// Returns whether every split was searched within the node budget, proving no split scores better than the ones kept,
+/// or `None` if the `deadline` passed first, leaving the best splits found so far in `best`.
+pub(super) fn improve<'a>(
+    players: &'a [Player],
+    num_teams: usize,
+    constraints: &Constraints,
+    count: usize,
+    best: &mut Vec<Candidate<'a>>,
+    deadline: Instant,
+    progress: &mut dyn FnMut(f32),
+) -> Option<bool> {
+    let (sizes, shared) = team_sizes(players.len(), num_teams, constraints.odd_roster);
+    let mut free = players.iter().filter(|p| p.fixed_team.is_none()).collect::<Vec<_>>();
+    free.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+    let mut suffix = vec![0.0; free.len() + 1];
+    for i in (0..free.len()).rev() {
+        suffix[i] = suffix[i + 1] + free[i].rating;
+    }
+
+    let mut search = Search {
+        constraints,
+        deadline,
+        progress,
+        out_of_time: false,
+        players,
+        free,
+        suffix,
+        bonus: compensation(&sizes, constraints),
+        sizes,
+        shared,
+        top_cutoff: top_cutoff(players, constraints.top_split),
+        count,
+        best: std::mem::take(best),
+        nodes: 0,
+    };
+    let mut teams = (0..num_teams)
+        .map(|t| Team {
+            players: players.iter().filter(|p| p.fixed_team == Some(t)).collect(),
+            half_player: None,
+        })
+        .collect::<Vec<_>>();
+    search.branch(&mut teams, 0);
+
+    *best = search.best;
+    (!search.out_of_time).then_some(search.nodes <= NODE_BUDGET)
+}
+
+impl<'a> Search<'a, '_> {
+    fn branch(&mut self, teams: &mut Vec<Team<'a>>, next: usize) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_EVERY) {
+            self.out_of_time |= Instant::now() > self.deadline;
+            (self.progress)(self.nodes as f32 / NODE_BUDGET as f32);
+        }
+        if self.nodes > NODE_BUDGET || self.out_of_time {
+            return;
+        }
+        if next == self.free.len() {
+            self.keep(teams);
+            return;
+        }
+        if let Some((worst, _, _)) = self.best.last().filter(|_| self.best.len() == self.count) {
+            if !is_better(self.bound(teams, next), worst.score) {
+                return;
+            }
+        }
+
+        let player = self.free[next];
+        let mut order = (0..teams.len())
+            .filter(|&t| teams[t].players.len() < self.sizes[t])
+            // Empty teams of the same size are interchangeable, so only the first of them is tried.
+            .filter(|&t| !teams[t].players.is_empty()
+                || !(0..t).any(|u| teams[u].players.is_empty() && self.sizes[u] == self.sizes[t]))
+            .collect::<Vec<_>>();
+        // Trying the weakest team first finds even splits early, which prunes the rest sooner.
+        order.sort_by(|&t, &u| rating(&teams[t]).total_cmp(&rating(&teams[u])));
+
+        for t in order {
+            teams[t].players.push(player);
+            self.branch(teams, next + 1);
+            teams[t].players.pop();
+        }
+
+        if self.shared && teams[0].half_player.is_none() {
+            for team in teams.iter_mut() {
+                team.half_player = Some(player);
+            }
+            self.branch(teams, next + 1);
+            for team in teams.iter_mut() {
+                team.half_player = None;
+            }
+        }
+    }
+
+    /// Keeps a complete split if it's among the best found so far.
+    fn keep(&mut self, teams: &[Team<'a>]) {
+        let score = score(teams, self.constraints);
+        if self.best.len() == self.count && self.best.last().is_some_and(|(worst, _, _)| !is_better(score, worst.score)) {
+            return;
+        }
+        let key = split_key(self.players, teams);
+        if self.best.iter().any(|(_, k, _)| *k == key) {
+            return;
+        }
+        let rank = Rank { score, delta: gap(teams, self.constraints), slack: slack(teams, self.constraints) };
+        self.best.push((rank, key, teams.to_vec()));
+        self.best.sort_by_key(|(rank, _, _)| *rank);
+        self.best.truncate(self.count);
+    }
+
+    /// The best score any split could get once the players from `next` on are dealt out.
+    fn bound(&self, teams: &[Team<'a>], next: usize) -> Score {
+        let left = self.free.len() - next;
+        let room = teams.iter()
+            .zip(&self.sizes)
+            .map(|(t, &size)| size - t.players.len())
+            .collect::<Vec<_>>();
+
+        // A team gets at least its share of the lowest rated players left, and at most its share of the highest.
+        let lowest = |t: usize| rating(&teams[t]) + self.bonus[t] + self.suffix[self.free.len() - room[t].min(left)];
+        let highest = |t: usize| rating(&teams[t]) + self.bonus[t] + self.suffix[next] - self.suffix[next + room[t].min(left)];
+        let gap = (0..teams.len())
+            .flat_map(|t| (0..teams.len()).filter(move |&u| u != t).map(move |u| (t, u)))
+            .map(|(t, u)| lowest(t) - highest(u))
+            .fold(0.0, f32::max);
+
+        Score {
+            violations: self.committed_violations(teams, &room),
+            over_delta: (gap - self.constraints.max_delta).max(0.0),
+            cost: gap,
+        }
+    }
+
+    /// Counts the violations a partial split already can't avoid, however the rest are dealt out.
+    fn committed_violations(&self, teams: &[Team<'a>], room: &[usize]) -> usize {
+        let constraints = self.constraints;
+        let mut violations = 0;
+
+        for (t, team) in teams.iter().enumerate() {
+            for (pos, &max) in &constraints.max_positions {
+                violations += position_count(&team.players, pos).saturating_sub(max);
+            }
+            for (pos, &req) in &constraints.min_positions {
+                violations += req.saturating_sub(position_count(&team.players, pos)).saturating_sub(room[t]);
+            }
+            let women = team.players.iter().filter(|p| p.gender).count();
+            let men = team.players.len() - women;
+            let needed = constraints.min_per_gender.saturating_sub(women) + constraints.min_per_gender.saturating_sub(men);
+            violations += needed.saturating_sub(room[t]);
+        }
+
+        if let Some(cutoff) = self.top_cutoff {
+            // Leaving out the shared player can only lower each team's share.
+            let top = self.players.iter().filter(|p| is_top(p, cutoff)).count();
+            let most = top.div_ceil(teams.len());
+            violations += teams.iter()
+                .map(|t| t.players.iter().filter(|p| is_top(p, cutoff)).count().saturating_sub(most))
+                .sum::<usize>();
+        }
+
+        if teams[0].half_player.is_some_and(|p| !constraints.shared_rules.allows(p)) {
+            violations += 1;
+        }
+
+        let team_of = |name: &str| teams.iter().position(|t| t.players.iter().any(|p| p.name == name));
+        let is_half = |name: &str| teams[0].half_player.is_some_and(|p| p.name == name);
+        violations += constraints.pairings.iter()
+            .filter(|rule| match (team_of(&rule.first), team_of(&rule.second)) {
+                (Some(first), Some(second)) => (first == second) != rule.together,
+                (Some(_), None) => is_half(&rule.second),
+                (None, Some(_)) => is_half(&rule.first),
+                (None, None) => false,
+            })
+            .count();
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The scores of every distinct split there is, best first.
+    fn brute_force(players: &[Player], num_teams: usize, constraints: &Constraints) -> Vec<(usize, f32, f32)> {
+        let (sizes, shared) = team_sizes(players.len(), num_teams, constraints.odd_roster);
+        let mut splits = BTreeMap::new();
+        let mut teams = vec![Team { players: vec![], half_player: None }; num_teams];
+        deal(players, 0, &sizes, shared, constraints, &mut teams, &mut splits);
+
+        let mut scores = splits.into_values()
+            .map(|s: Score| (s.violations, s.over_delta, s.cost))
+            .collect::<Vec<_>>();
+        scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        scores
+    }
+
+    fn deal<'a>(
+        players: &'a [Player],
+        next: usize,
+        sizes: &[usize],
+        shared: bool,
+        constraints: &Constraints,
+        teams: &mut Vec<Team<'a>>,
+        splits: &mut BTreeMap<Vec<Vec<usize>>, Score>,
+    ) {
+        let Some(player) = players.get(next) else {
+            splits.insert(split_key(players, teams), score(teams, constraints));
+            return;
+        };
+        for t in 0..teams.len() {
+            if teams[t].players.len() < sizes[t] && player.fixed_team.is_none_or(|f| f == t) {
+                teams[t].players.push(player);
+                deal(players, next + 1, sizes, shared, constraints, teams, splits);
+                teams[t].players.pop();
+            }
+        }
+        if shared && player.fixed_team.is_none() && teams[0].half_player.is_none() {
+            teams.iter_mut().for_each(|t| t.half_player = Some(player));
+            deal(players, next + 1, sizes, shared, constraints, teams, splits);
+            teams.iter_mut().for_each(|t| t.half_player = None);
+        }
+    }
+
+    /// A small roster with whole number ratings, so every way of adding them up gives the same total.
+    fn random_roster(rng: &mut ChaCha8Rng) -> (Vec<Player>, usize, Constraints) {
+        let num_teams = rng.gen_range(2..=3);
+        let players = (0..rng.gen_range(num_teams * 2..=8))
+            .map(|i| Player {
+                name: format!("P{i}"),
+                rating: rng.gen_range(1..=10) as f32,
+                gender: rng.gen_bool(0.5),
+                fixed_team: None,
+                position: Some(vec![["gk", "df", "mid", "fw"][rng.gen_range(0..4)].to_string()]),
+                pairings: vec![],
+                attributes: BTreeMap::new(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut constraints = Constraints {
+            max_delta: rng.gen_range(0..4) as f32,
+            odd_roster: [OddRoster::Shared, OddRoster::Uneven(1.0), OddRoster::Substitute][rng.gen_range(0..3)],
+            ..Constraints::default()
+        };
+        if rng.gen_bool(0.5) {
+            constraints.min_positions.insert("gk".to_string(), 1);
+        }
+        if rng.gen_bool(0.5) {
+            constraints.max_gender_delta = Some(rng.gen_range(0..2));
+        }
+        if rng.gen_bool(0.5) {
+            constraints.top_split = rng.gen_range(2..=4);
+        }
+        if rng.gen_bool(0.5) {
+            constraints.pairings.push(Pairing {
+                first: players[0].name.clone(),
+                second: players[1].name.clone(),
+                together: rng.gen_bool(0.5),
+            });
+        }
+        let mut players = players;
+        if rng.gen_bool(0.3) {
+            let i = rng.gen_range(0..players.len());
+            players[i].fixed_team = Some(rng.gen_range(0..num_teams));
+        }
+        (players, num_teams, constraints)
+    }
+
+    #[test]
+    fn finds_the_best_splits_there_are() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        for _ in 0..100 {
+            let (players, num_teams, constraints) = random_roster(&mut rng);
+            let count = rng.gen_range(1..=3);
+            let mut best = vec![];
+            let deadline = Instant::now() + Duration::from_secs(60);
+            let proven = improve(&players, num_teams, &constraints, count, &mut best, deadline, &mut |_| {});
+            assert_eq!(proven, Some(true));
+
+            let found = best.iter()
+                .map(|(rank, _, _)| (rank.score.violations, rank.score.over_delta, rank.score.cost))
+                .collect::<Vec<_>>();
+            let expected = brute_force(&players, num_teams, &constraints);
+            assert_eq!(found, expected[..count.min(expected.len())]);
+        }
+    }
+
+    #[test]
+    fn keeps_improving_on_the_splits_it_is_given() {
+        let mut rng = ChaCha8Rng::seed_from_u64(11);
+        for _ in 0..20 {
+            let (players, num_teams, constraints) = random_roster(&mut rng);
+            let teams = random_team(&players, num_teams, constraints.odd_roster, &mut rng);
+            let rank = Rank { score: score(&teams, &constraints), delta: gap(&teams, &constraints), slack: slack(&teams, &constraints) };
+            let mut best = vec![(rank, split_key(&players, &teams), teams)];
+            let deadline = Instant::now() + Duration::from_secs(60);
+            improve(&players, num_teams, &constraints, 1, &mut best, deadline, &mut |_| {});
+
+            let expected = brute_force(&players, num_teams, &constraints)[0];
+            let found = best[0].0.score;
+            assert_eq!((found.violations, found.over_delta, found.cost), expected);
+        }
+    }
+
+    #[test]
+    fn gives_up_once_the_deadline_passes() {
+        let players = (0..MAX_PLAYERS)
+            .map(|i| Player {
+                name: format!("P{i}"),
+                rating: (i * 7 % 11) as f32,
+                gender: false,
+                fixed_team: None,
+                position: None,
+                pairings: vec![],
+                attributes: BTreeMap::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut best = vec![];
+        let proven = improve(&players, 3, &Constraints::default(), 1, &mut best, Instant::now(), &mut |_| {});
+        assert_eq!(proven, None);
+    }
+}
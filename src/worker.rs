@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{DedicatedWorkerGlobalScope, ErrorEvent, Event, MessageEvent, Worker};
+use web_time::Duration;
+
+use crate::teamgen::{adjust_teams, get_even_teams, Adjustment, Constraints, GeneratedTeams, Player};
+
+/// The script Trunk builds to load the worker binary, from the link's `data-loader-shim`.
+const WORKER_SCRIPT: &str = "./worker_loader.js";
+
+/// A team generation run, as sent to the worker.
+#[derive(Serialize, Deserialize)]
+pub struct Request {
+    pub players: Vec<Player>,
+    pub num_teams: usize,
+    pub constraints: Constraints,
+    /// How many candidate splits to return
+    pub count: usize,
+    pub seed: Option<u64>,
+    /// How long the search may take before it gives up
+    pub budget: Duration,
+}
+
+/// Players arriving at or leaving the current teams, as sent to the worker.
+#[derive(Serialize, Deserialize)]
+pub struct AdjustRequest {
+    pub teams: Vec<Vec<Player>>,
+    pub shared: Option<Player>,
+    pub arrivals: Vec<Player>,
+    pub departures: Vec<String>,
+    pub constraints: Constraints,
+}
+
+/// Everything the worker can be asked to do.
+#[derive(Serialize, Deserialize)]
+pub enum Job {
+    Generate(Request),
+    Adjust(AdjustRequest),
+}
+
+/// What the worker sends back while generating.
+#[derive(Serialize, Deserialize)]
+pub enum Response {
+    /// The worker has loaded and is listening for a request
+    Ready,
+    /// How far along the search is, from 0 to 1
+    Progress(f32),
+    Done(Result<Vec<GeneratedTeams>, String>),
+    Adjusted(Result<Adjustment, String>),
+}
+
+/// Messages are posted as JSON, which keeps 64-bit seeds exact.
+fn encode(message: &impl Serialize) -> JsValue {
+    JsValue::from_str(&serde_json::to_string(message).expect("messages serialize to JSON"))
+}
+
+fn receive<T: for<'de> Deserialize<'de>>(ev: &MessageEvent) -> Option<T> {
+    ev.data().as_string().and_then(|message| serde_json::from_str(&message).ok())
+}
+
+/// Answers every job posted to this worker, with a team generation run's progress and then its result.
+pub fn serve() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let reply = scope.clone();
+    let respond = move |response: &Response| reply.post_message(&encode(response)).expect("strings can always be posted");
+
+    let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |ev: MessageEvent| {
+        match receive::<Job>(&ev) {
+            Some(Job::Generate(request)) => {
+                let result = get_even_teams(
+                    &request.players,
+                    request.num_teams,
+                    &request.constraints,
+                    request.count,
+                    request.seed,
+                    request.budget,
+                    |done| respond(&Response::Progress(done)),
+                );
+                respond(&Response::Done(result));
+            },
+            Some(Job::Adjust(request)) => {
+                let result = adjust_teams(
+                    &request.teams,
+                    request.shared.as_ref(),
+                    &request.arrivals,
+                    &request.departures,
+                    &request.constraints,
+                );
+                respond(&Response::Adjusted(result));
+            },
+            None => {},
+        }
+    });
+    scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    scope.post_message(&encode(&Response::Ready)).expect("strings can always be posted");
+}
+
+/// A job running in its own worker, which is stopped when this is dropped.
+pub struct Generation {
+    worker: Worker,
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+    _onerror: Closure<dyn FnMut(Event)>,
+}
+
+impl Generation {
+    /// Starts a worker on the `job`, passing everything it sends back but [`Response::Ready`] to `on_response`.
+    ///
+    /// A worker that fails to load or panics is reported as a failed [`Response::Done`] or [`Response::Adjusted`], whichever the job would send.
+    pub fn start(job: Job, on_response: impl FnMut(Response) + 'static) -> Result<Self, JsValue> {
+        let worker = Worker::new(WORKER_SCRIPT)?;
+        let on_response = Rc::new(RefCell::new(on_response));
+        let failed: fn(String) -> Response = match job {
+            Job::Generate(_) => |reason| Response::Done(Err(reason)),
+            Job::Adjust(_) => |reason| Response::Adjusted(Err(reason)),
+        };
+
+        let sender = worker.clone();
+        let respond = on_response.clone();
+        let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |ev: MessageEvent| {
+            match receive::<Response>(&ev) {
+                // Anything sent before the worker is listening would be lost.
+                Some(Response::Ready) => sender.post_message(&encode(&job)).expect("strings can always be posted"),
+                Some(response) => (respond.borrow_mut())(response),
+                None => {},
+            }
+        });
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+
+        // Only errors thrown by the worker's code carry a message; a script that won't load is a plain event.
+        let onerror = Closure::<dyn FnMut(Event)>::new(move |ev: Event| {
+            let reason = ev.dyn_ref::<ErrorEvent>()
+                .map(ErrorEvent::message)
+                .unwrap_or_else(|| format!("{WORKER_SCRIPT} couldn't be loaded"));
+            (on_response.borrow_mut())(failed(format!("The team generator stopped: {reason}")));
+        });
+        worker.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+
+        Ok(Self { worker, _onmessage: onmessage, _onerror: onerror })
+    }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        self.worker.terminate();
+    }
+}